@@ -37,6 +37,10 @@ pub struct Cli {
     /// Suppress error messages (useful for transient prompts to avoid duplication)
     #[arg(long)]
     pub quiet: bool,
+
+    /// Skip the compiled theme cache and re-read the theme on every render
+    #[arg(long)]
+    pub no_cache: bool,
 }
 
 #[derive(Subcommand, Debug)]
@@ -76,6 +80,19 @@ pub enum Commands {
         command: HistoryCommands,
     },
 
+    /// Theme development tools
+    Theme {
+        #[command(subcommand)]
+        command: ThemeCommands,
+    },
+
+    /// Measure how long loading the active theme takes, with and without the cache
+    Bench {
+        /// Number of loads to average over
+        #[arg(short = 'n', long, default_value = "100")]
+        iterations: u32,
+    },
+
     /// Generate shell completions
     Completions {
         /// Shell to generate completions for
@@ -98,6 +115,33 @@ pub enum Commands {
         #[arg(long)]
         signal_file: Option<String>,
     },
+
+    /// Internal: keep the git status cache current from file events (not for direct use)
+    #[cfg(feature = "git-watcher")]
+    #[command(name = "_internal-git-watch", hide = true)]
+    InternalGitWatch {
+        /// Repository root path
+        #[arg(long)]
+        repo_path: String,
+
+        /// Cache file path to keep updated
+        #[arg(long)]
+        cache_path: String,
+
+        /// Signal file to touch after the first scan (notifies Zsh)
+        #[arg(long)]
+        signal_file: Option<String>,
+    },
+}
+
+/// Theme subcommands
+#[derive(Subcommand, Debug)]
+pub enum ThemeCommands {
+    /// Check a theme's templates for errors (exits non-zero if any are found)
+    Check {
+        /// Path to the theme TOML file
+        file: PathBuf,
+    },
 }
 
 /// History subcommands
@@ -262,4 +306,15 @@ mod tests {
             _ => panic!("Expected Prompt command"),
         }
     }
+
+    #[test]
+    fn test_theme_check_command() {
+        let cli = Cli::parse_from(["zush-prompt", "theme", "check", "themes/split.toml"]);
+        match cli.command {
+            Some(Commands::Theme {
+                command: ThemeCommands::Check { file },
+            }) => assert_eq!(file, PathBuf::from("themes/split.toml")),
+            _ => panic!("Expected Theme Check command"),
+        }
+    }
 }
@@ -1,10 +1,14 @@
 #![allow(dead_code)]
 
+mod palette;
+
 use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
 use std::fmt;
 use std::str::FromStr;
 
+pub use palette::Palette;
+
 /// Represents a 24-bit RGB color
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Color {
@@ -88,6 +92,414 @@ impl Color {
             b: (self.b as f32 * (1.0 - ratio) + other.b as f32 * ratio) as u8,
         }
     }
+
+    /// WCAG relative luminance (0.0 for black to 1.0 for white)
+    pub fn relative_luminance(&self) -> f64 {
+        fn channel(c: u8) -> f64 {
+            let c = c as f64 / 255.0;
+            if c <= 0.03928 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        }
+        0.2126 * channel(self.r) + 0.7152 * channel(self.g) + 0.0722 * channel(self.b)
+    }
+
+    /// WCAG contrast ratio against another color (1.0 to 21.0)
+    pub fn contrast_ratio(&self, other: &Color) -> f64 {
+        let a = self.relative_luminance();
+        let b = other.relative_luminance();
+        (a.max(b) + 0.05) / (a.min(b) + 0.05)
+    }
+
+    /// Pick the most readable candidate to draw on top of this color
+    pub fn best_contrast(&self, candidates: &[Color]) -> Option<Color> {
+        candidates
+            .iter()
+            .copied()
+            .max_by(|a, b| self.contrast_ratio(a).total_cmp(&self.contrast_ratio(b)))
+    }
+
+    /// Readable foreground (black or white) for this background
+    pub fn contrast(&self) -> Self {
+        self.best_contrast(&[Color::new(0, 0, 0), Color::new(255, 255, 255)])
+            .unwrap_or(*self)
+    }
+
+    /// Evaluate a color expression such as `darken(bg_git,0.3)`, `lighten(#7aa2f7,20%)`,
+    /// `mix(red,blue,0.5)` or `contrast(bg_user)`. Calls can be nested.
+    /// Plain tokens (hex codes or names) are passed to `resolve`.
+    pub fn parse_expression<F>(expr: &str, resolve: &F) -> Result<Self>
+    where
+        F: Fn(&str) -> Result<Color>,
+    {
+        let expr = expr.trim();
+        let Some(open) = expr.find('(') else {
+            return resolve(expr);
+        };
+        if !expr.ends_with(')') {
+            return Err(anyhow!("Unclosed color function: {}", expr));
+        }
+
+        let func = expr[..open].trim();
+        let args = split_args(&expr[open + 1..expr.len() - 1]);
+        let amount = |arg: &str| {
+            parse_amount(arg).ok_or_else(|| anyhow!("Invalid amount '{}' in {}", arg, expr))
+        };
+
+        match (func, args.as_slice()) {
+            ("lighten", [color, value]) => {
+                Ok(Self::parse_expression(color, resolve)?.lighten(amount(value)?))
+            }
+            ("darken", [color, value]) => {
+                Ok(Self::parse_expression(color, resolve)?.darken(amount(value)?))
+            }
+            ("mix", [a, b, value]) => {
+                let a = Self::parse_expression(a, resolve)?;
+                let b = Self::parse_expression(b, resolve)?;
+                Ok(a.mix(&b, amount(value)?))
+            }
+            ("contrast", [color]) => Ok(Self::parse_expression(color, resolve)?.contrast()),
+            _ => Err(anyhow!(
+                "Invalid color function '{}'. Use lighten(color,amount), darken(color,amount), mix(a,b,ratio) or contrast(color)",
+                expr
+            )),
+        }
+    }
+}
+
+/// Parse an amount like "0.3" or "30%" into 0.0-1.0
+pub fn parse_amount(s: &str) -> Option<f32> {
+    let s = s.trim();
+    match s.strip_suffix('%') {
+        Some(percent) => percent.trim().parse::<f32>().ok().map(|p| p / 100.0),
+        None => s.parse().ok(),
+    }
+}
+
+/// Split function arguments on top-level commas (ignores commas inside nested calls)
+fn split_args(args: &str) -> Vec<&str> {
+    let mut result = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+
+    for (i, ch) in args.char_indices() {
+        match ch {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                result.push(args[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if !args[start..].trim().is_empty() || !result.is_empty() {
+        result.push(args[start..].trim());
+    }
+
+    result
+}
+
+/// Maps a numeric value to a color using ordered stops
+/// Values between two stops are interpolated with `Color::mix`,
+/// values outside the range clamp to the first/last stop
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorScale {
+    stops: Vec<(f64, Color)>,
+}
+
+impl ColorScale {
+    /// Parse a stop list like "0:green 1000:yellow 5000:red"
+    /// `resolve` maps each color token (name or hex) to a Color
+    pub fn parse<F>(spec: &str, resolve: F) -> Result<Self>
+    where
+        F: Fn(&str) -> Option<Color>,
+    {
+        let mut stops = Vec::new();
+
+        for stop in spec.split_whitespace() {
+            let (value, color) = stop
+                .split_once(':')
+                .ok_or_else(|| anyhow!("Invalid scale stop '{}', expected value:color", stop))?;
+            let value: f64 = value
+                .parse()
+                .map_err(|_| anyhow!("Invalid scale stop value '{}' in '{}'", value, stop))?;
+            let color =
+                resolve(color).ok_or_else(|| anyhow!("Unknown scale stop color '{}'", color))?;
+            stops.push((value, color));
+        }
+
+        if stops.is_empty() {
+            return Err(anyhow!(
+                "Color scale requires at least one value:color stop"
+            ));
+        }
+
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Ok(Self { stops })
+    }
+
+    /// Get the color for a value
+    pub fn at(&self, value: f64) -> Color {
+        let (first_value, first_color) = self.stops[0];
+        if value.is_nan() || value <= first_value {
+            return first_color;
+        }
+
+        for pair in self.stops.windows(2) {
+            let (lo, lo_color) = pair[0];
+            let (hi, hi_color) = pair[1];
+            if value <= hi {
+                if hi <= lo {
+                    return hi_color;
+                }
+                let ratio = ((value - lo) / (hi - lo)) as f32;
+                return lo_color.mix(&hi_color, ratio);
+            }
+        }
+
+        self.stops[self.stops.len() - 1].1
+    }
+}
+
+/// Color space used when interpolating between two colors
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorSpace {
+    /// Straight sRGB channel interpolation
+    #[default]
+    Rgb,
+    /// Perceptually uniform, avoids the muddy midpoints of RGB
+    Oklab,
+    /// Hue rotation along the shorter way around the color wheel
+    Hsl,
+}
+
+impl ColorSpace {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "rgb" | "srgb" => Some(ColorSpace::Rgb),
+            "oklab" => Some(ColorSpace::Oklab),
+            "hsl" => Some(ColorSpace::Hsl),
+            _ => None,
+        }
+    }
+}
+
+impl Color {
+    /// Interpolate towards `other` (t = 0.0 is self, 1.0 is other) in the given space
+    pub fn interpolate(&self, other: &Color, t: f32, space: ColorSpace) -> Self {
+        let t = t.clamp(0.0, 1.0);
+        let lerp = |a: f32, b: f32| a + (b - a) * t;
+
+        match space {
+            ColorSpace::Rgb => Self {
+                r: lerp(self.r as f32, other.r as f32).round() as u8,
+                g: lerp(self.g as f32, other.g as f32).round() as u8,
+                b: lerp(self.b as f32, other.b as f32).round() as u8,
+            },
+            ColorSpace::Oklab => {
+                let (l1, a1, b1) = self.to_oklab();
+                let (l2, a2, b2) = other.to_oklab();
+                Self::from_oklab(lerp(l1, l2), lerp(a1, a2), lerp(b1, b2))
+            }
+            ColorSpace::Hsl => {
+                let (h1, s1, l1) = self.to_hsl();
+                let (h2, s2, l2) = other.to_hsl();
+                // Take the shorter way around the hue circle
+                let mut delta = h2 - h1;
+                if delta > 180.0 {
+                    delta -= 360.0;
+                } else if delta < -180.0 {
+                    delta += 360.0;
+                }
+                let hue = (h1 + delta * t).rem_euclid(360.0);
+                Self::from_hsl(hue, lerp(s1, s2), lerp(l1, l2))
+            }
+        }
+    }
+
+    /// Convert to OKLab (L, a, b)
+    fn to_oklab(self) -> (f32, f32, f32) {
+        fn linear(c: u8) -> f32 {
+            let c = c as f32 / 255.0;
+            if c <= 0.04045 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        }
+        let (r, g, b) = (linear(self.r), linear(self.g), linear(self.b));
+
+        let l = (0.412_221_46 * r + 0.536_332_55 * g + 0.051_445_995 * b).cbrt();
+        let m = (0.211_903_5 * r + 0.680_699_5 * g + 0.107_396_96 * b).cbrt();
+        let s = (0.088_302_46 * r + 0.281_718_85 * g + 0.629_978_7 * b).cbrt();
+
+        (
+            0.210_454_26 * l + 0.793_617_8 * m - 0.004_072_047 * s,
+            1.977_998_5 * l - 2.428_592_2 * m + 0.450_593_7 * s,
+            0.025_904_037 * l + 0.782_771_77 * m - 0.808_675_77 * s,
+        )
+    }
+
+    /// Convert from OKLab, clamping out-of-gamut results
+    fn from_oklab(l: f32, a: f32, b: f32) -> Self {
+        fn encode(c: f32) -> u8 {
+            let c = if c <= 0.003_130_8 {
+                12.92 * c
+            } else {
+                1.055 * c.powf(1.0 / 2.4) - 0.055
+            };
+            (c.clamp(0.0, 1.0) * 255.0).round() as u8
+        }
+
+        let l_ = (l + 0.396_337_78 * a + 0.215_803_76 * b).powi(3);
+        let m_ = (l - 0.105_561_346 * a - 0.063_854_17 * b).powi(3);
+        let s_ = (l - 0.089_484_18 * a - 1.291_485_5 * b).powi(3);
+
+        Self {
+            r: encode(4.076_741_7 * l_ - 3.307_711_6 * m_ + 0.230_969_94 * s_),
+            g: encode(-1.268_438 * l_ + 2.609_757_4 * m_ - 0.341_319_38 * s_),
+            b: encode(-0.004_196_086_3 * l_ - 0.703_418_6 * m_ + 1.707_614_7 * s_),
+        }
+    }
+
+    /// Convert to HSL (hue in degrees, saturation and lightness 0.0-1.0)
+    fn to_hsl(self) -> (f32, f32, f32) {
+        let (r, g, b) = (
+            self.r as f32 / 255.0,
+            self.g as f32 / 255.0,
+            self.b as f32 / 255.0,
+        );
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let l = (max + min) / 2.0;
+        let d = max - min;
+
+        if d == 0.0 {
+            return (0.0, 0.0, l);
+        }
+
+        let s = d / (1.0 - (2.0 * l - 1.0).abs());
+        let h = if max == r {
+            60.0 * ((g - b) / d).rem_euclid(6.0)
+        } else if max == g {
+            60.0 * ((b - r) / d + 2.0)
+        } else {
+            60.0 * ((r - g) / d + 4.0)
+        };
+        (h, s, l)
+    }
+
+    fn from_hsl(h: f32, s: f32, l: f32) -> Self {
+        let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+        let x = c * (1.0 - ((h / 60.0).rem_euclid(2.0) - 1.0).abs());
+        let m = l - c / 2.0;
+        let (r, g, b) = match h as u32 {
+            0..=59 => (c, x, 0.0),
+            60..=119 => (x, c, 0.0),
+            120..=179 => (0.0, c, x),
+            180..=239 => (0.0, x, c),
+            240..=299 => (x, 0.0, c),
+            _ => (c, 0.0, x),
+        };
+        let channel = |v: f32| ((v + m).clamp(0.0, 1.0) * 255.0).round() as u8;
+        Self {
+            r: channel(r),
+            g: channel(g),
+            b: channel(b),
+        }
+    }
+}
+
+/// A gradient through evenly spaced color stops
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gradient {
+    stops: Vec<Color>,
+    space: ColorSpace,
+}
+
+impl Gradient {
+    pub fn new(stops: Vec<Color>, space: ColorSpace) -> Result<Self> {
+        if stops.len() < 2 {
+            return Err(anyhow!("Gradient requires at least two colors"));
+        }
+        Ok(Self { stops, space })
+    }
+
+    /// Color at position t (0.0 = first stop, 1.0 = last stop)
+    pub fn at(&self, t: f64) -> Color {
+        let t = t.clamp(0.0, 1.0);
+        let segments = self.stops.len() - 1;
+        let scaled = t * segments as f64;
+        let index = (scaled.floor() as usize).min(segments - 1);
+        let local = (scaled - index as f64) as f32;
+        self.stops[index].interpolate(&self.stops[index + 1], local, self.space)
+    }
+
+    /// Color text character by character, as foreground or background
+    /// ANSI escape sequences already in the text pass through untouched and
+    /// do not count as positions, so styled content keeps an even gradient.
+    pub fn apply(&self, text: &str, background: bool) -> String {
+        let visible = strip_ansi_chars(text).filter(|c| *c != '\n').count();
+        let mut output = String::new();
+        let mut index = 0;
+        let mut chars = text.chars().peekable();
+
+        while let Some(ch) = chars.next() {
+            if ch == '\x1b' {
+                output.push(ch);
+                if chars.peek() == Some(&'[') {
+                    for next in chars.by_ref() {
+                        output.push(next);
+                        if next.is_ascii_alphabetic() {
+                            break;
+                        }
+                    }
+                }
+                continue;
+            }
+            if ch == '\n' {
+                output.push(ch);
+                continue;
+            }
+
+            let t = if visible <= 1 {
+                0.0
+            } else {
+                index as f64 / (visible - 1) as f64
+            };
+            let color = self.at(t);
+            if background {
+                output.push_str(&color.to_ansi_bg());
+            } else {
+                output.push_str(&color.to_ansi_fg());
+            }
+            output.push(ch);
+            index += 1;
+        }
+
+        output
+    }
+}
+
+/// Characters of `text` outside ANSI CSI escape sequences
+fn strip_ansi_chars(text: &str) -> impl Iterator<Item = char> + '_ {
+    let mut in_escape = false;
+    text.chars().filter(move |&c| {
+        if in_escape {
+            if c.is_ascii_alphabetic() {
+                in_escape = false;
+            }
+            false
+        } else if c == '\x1b' {
+            in_escape = true;
+            false
+        } else {
+            true
+        }
+    })
 }
 
 impl FromStr for Color {
@@ -230,7 +642,6 @@ pub mod tokyo_night {
 }
 
 /// Color scheme trait for custom themes
-#[allow(dead_code)]
 pub trait ColorScheme {
     fn background(&self) -> Color;
     fn foreground(&self) -> Color;
@@ -242,6 +653,22 @@ pub trait ColorScheme {
     fn magenta(&self) -> Color;
     fn cyan(&self) -> Color;
     fn white(&self) -> Color;
+
+    /// Every color keyed by its role name, as exposed to templates
+    fn entries(&self) -> Vec<(&'static str, Color)> {
+        vec![
+            ("background", self.background()),
+            ("foreground", self.foreground()),
+            ("black", self.black()),
+            ("red", self.red()),
+            ("green", self.green()),
+            ("yellow", self.yellow()),
+            ("blue", self.blue()),
+            ("magenta", self.magenta()),
+            ("cyan", self.cyan()),
+            ("white", self.white()),
+        ]
+    }
 }
 
 /// ANSI escape code helpers
@@ -332,4 +759,153 @@ mod tests {
         let darker = color.darken(0.5);
         assert!(darker.r < color.r);
     }
+
+    #[test]
+    fn test_color_scale() {
+        let scale =
+            ColorScale::parse("0:#000000 100:#ffffff", |c| Color::from_hex(c).ok()).unwrap();
+        assert_eq!(scale.at(-5.0), Color::new(0, 0, 0));
+        assert_eq!(scale.at(50.0), Color::new(127, 127, 127));
+        assert_eq!(scale.at(500.0), Color::new(255, 255, 255));
+    }
+
+    #[test]
+    fn test_color_scale_multiple_stops() {
+        // Stops are sorted, so order in the spec doesn't matter
+        let scale = ColorScale::parse("5000:#ff0000 0:#00ff00 1000:#ffff00", |c| {
+            Color::from_hex(c).ok()
+        })
+        .unwrap();
+        assert_eq!(scale.at(0.0), Color::new(0, 255, 0));
+        assert_eq!(scale.at(1000.0), Color::new(255, 255, 0));
+        assert_eq!(scale.at(3000.0), Color::new(255, 127, 0));
+        assert_eq!(scale.at(9000.0), Color::new(255, 0, 0));
+    }
+
+    #[test]
+    fn test_color_scale_invalid() {
+        let resolve = |c: &str| Color::from_hex(c).ok();
+        assert!(ColorScale::parse("", resolve).is_err());
+        assert!(ColorScale::parse("0-green", resolve).is_err());
+        assert!(ColorScale::parse("abc:#ffffff", resolve).is_err());
+        assert!(ColorScale::parse("0:nope", resolve).is_err());
+    }
+
+    #[test]
+    fn test_relative_luminance_and_contrast() {
+        let black = Color::new(0, 0, 0);
+        let white = Color::new(255, 255, 255);
+        assert!(black.relative_luminance() < 0.001);
+        assert!((white.relative_luminance() - 1.0).abs() < 0.001);
+        assert!((black.contrast_ratio(&white) - 21.0).abs() < 0.01);
+
+        // Dark backgrounds get white text, light backgrounds get black text
+        assert_eq!(Color::from_hex("#1a1b26").unwrap().contrast(), white);
+        assert_eq!(Color::from_hex("#e0af68").unwrap().contrast(), black);
+    }
+
+    #[test]
+    fn test_color_expressions() {
+        let resolve = |name: &str| -> Result<Color> {
+            match name {
+                "grey" => Ok(Color::new(100, 100, 100)),
+                other => Color::from_hex(other),
+            }
+        };
+
+        assert_eq!(
+            Color::parse_expression("grey", &resolve).unwrap(),
+            Color::new(100, 100, 100)
+        );
+        assert_eq!(
+            Color::parse_expression("darken(grey,0.5)", &resolve).unwrap(),
+            Color::new(50, 50, 50)
+        );
+        assert_eq!(
+            Color::parse_expression("lighten(#000000, 20%)", &resolve).unwrap(),
+            Color::new(51, 51, 51)
+        );
+        assert_eq!(
+            Color::parse_expression("mix(#000000,#ffffff,0.5)", &resolve).unwrap(),
+            Color::new(127, 127, 127)
+        );
+        assert_eq!(
+            Color::parse_expression("contrast(darken(grey,0.5))", &resolve).unwrap(),
+            Color::new(255, 255, 255)
+        );
+
+        assert!(Color::parse_expression("darken(grey)", &resolve).is_err());
+        assert!(Color::parse_expression("blur(grey,1)", &resolve).is_err());
+        assert!(Color::parse_expression("darken(grey,lots)", &resolve).is_err());
+        assert!(Color::parse_expression("darken(nope,0.1)", &resolve).is_err());
+    }
+
+    #[test]
+    fn test_interpolate_spaces() {
+        let black = Color::new(0, 0, 0);
+        let white = Color::new(255, 255, 255);
+        let red = Color::new(255, 0, 0);
+        let blue = Color::new(0, 0, 255);
+
+        for space in [ColorSpace::Rgb, ColorSpace::Oklab, ColorSpace::Hsl] {
+            assert_eq!(red.interpolate(&blue, 0.0, space), red);
+            assert_eq!(red.interpolate(&blue, 1.0, space), blue);
+        }
+
+        assert_eq!(
+            black.interpolate(&white, 0.5, ColorSpace::Rgb),
+            Color::new(128, 128, 128)
+        );
+        // OKLab lightness is perceptual, so its midpoint is darker than RGB's
+        assert_eq!(
+            black.interpolate(&white, 0.5, ColorSpace::Oklab),
+            Color::new(99, 99, 99)
+        );
+        // HSL goes the short way round from red (0deg) to blue (240deg), through magenta
+        assert_eq!(
+            red.interpolate(&blue, 0.5, ColorSpace::Hsl),
+            Color::new(255, 0, 255)
+        );
+    }
+
+    #[test]
+    fn test_color_space_parse() {
+        assert_eq!(ColorSpace::parse("OKLab"), Some(ColorSpace::Oklab));
+        assert_eq!(ColorSpace::parse("hsl"), Some(ColorSpace::Hsl));
+        assert_eq!(ColorSpace::parse("rgb"), Some(ColorSpace::Rgb));
+        assert_eq!(ColorSpace::parse("cmyk"), None);
+    }
+
+    #[test]
+    fn test_gradient_stops() {
+        let red = Color::new(255, 0, 0);
+        let green = Color::new(0, 255, 0);
+        let blue = Color::new(0, 0, 255);
+        let gradient = Gradient::new(vec![red, green, blue], ColorSpace::Rgb).unwrap();
+
+        assert_eq!(gradient.at(0.0), red);
+        assert_eq!(gradient.at(0.5), green);
+        assert_eq!(gradient.at(1.0), blue);
+        assert_eq!(gradient.at(0.25), Color::new(128, 128, 0));
+        assert!(Gradient::new(vec![red], ColorSpace::Rgb).is_err());
+    }
+
+    #[test]
+    fn test_gradient_apply_skips_escapes() {
+        let gradient = Gradient::new(
+            vec![Color::new(0, 0, 0), Color::new(255, 255, 255)],
+            ColorSpace::Rgb,
+        )
+        .unwrap();
+
+        // Bold codes are passed through and not counted as positions
+        let output = gradient.apply("a\x1b[1mb\x1b[22mc", false);
+        assert_eq!(
+            output,
+            "\x1b[38;2;0;0;0ma\x1b[1m\x1b[38;2;128;128;128mb\x1b[22m\x1b[38;2;255;255;255mc"
+        );
+
+        let output = gradient.apply("ab", true);
+        assert_eq!(output, "\x1b[48;2;0;0;0ma\x1b[48;2;255;255;255mb");
+    }
 }
@@ -0,0 +1,280 @@
+//! Built-in named palettes
+//!
+//! A palette is selected with `palette = "catppuccin-mocha"` in the config or
+//! theme and exposed to templates as `palette.<color>`, so a theme written
+//! against `palette.red` recolors itself when the palette changes.
+
+use super::{tokyo_night, Color, ColorScheme};
+use anyhow::{anyhow, Result};
+
+/// A named palette of the ten standard terminal roles
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette {
+    pub name: &'static str,
+    background: Color,
+    foreground: Color,
+    black: Color,
+    red: Color,
+    green: Color,
+    yellow: Color,
+    blue: Color,
+    magenta: Color,
+    cyan: Color,
+    white: Color,
+}
+
+const fn rgb(hex: u32) -> Color {
+    Color {
+        r: (hex >> 16) as u8,
+        g: (hex >> 8) as u8,
+        b: hex as u8,
+    }
+}
+
+/// Every built-in palette, looked up by name with [`Palette::find`]
+pub const PALETTES: &[Palette] = &[
+    Palette {
+        name: "catppuccin-latte",
+        background: rgb(0xeff1f5),
+        foreground: rgb(0x4c4f69),
+        black: rgb(0x5c5f77),
+        red: rgb(0xd20f39),
+        green: rgb(0x40a02b),
+        yellow: rgb(0xdf8e1d),
+        blue: rgb(0x1e66f5),
+        magenta: rgb(0xea76cb),
+        cyan: rgb(0x179299),
+        white: rgb(0xacb0be),
+    },
+    Palette {
+        name: "catppuccin-frappe",
+        background: rgb(0x303446),
+        foreground: rgb(0xc6d0f5),
+        black: rgb(0x51576d),
+        red: rgb(0xe78284),
+        green: rgb(0xa6d189),
+        yellow: rgb(0xe5c890),
+        blue: rgb(0x8caaee),
+        magenta: rgb(0xf4b8e4),
+        cyan: rgb(0x81c8be),
+        white: rgb(0xb5bfe2),
+    },
+    Palette {
+        name: "catppuccin-macchiato",
+        background: rgb(0x24273a),
+        foreground: rgb(0xcad3f5),
+        black: rgb(0x494d64),
+        red: rgb(0xed8796),
+        green: rgb(0xa6da95),
+        yellow: rgb(0xeed49f),
+        blue: rgb(0x8aadf4),
+        magenta: rgb(0xf5bde6),
+        cyan: rgb(0x8bd5ca),
+        white: rgb(0xb8c0e0),
+    },
+    Palette {
+        name: "catppuccin-mocha",
+        background: rgb(0x1e1e2e),
+        foreground: rgb(0xcdd6f4),
+        black: rgb(0x45475a),
+        red: rgb(0xf38ba8),
+        green: rgb(0xa6e3a1),
+        yellow: rgb(0xf9e2af),
+        blue: rgb(0x89b4fa),
+        magenta: rgb(0xf5c2e7),
+        cyan: rgb(0x94e2d5),
+        white: rgb(0xbac2de),
+    },
+    Palette {
+        name: "nord",
+        background: rgb(0x2e3440),
+        foreground: rgb(0xd8dee9),
+        black: rgb(0x3b4252),
+        red: rgb(0xbf616a),
+        green: rgb(0xa3be8c),
+        yellow: rgb(0xebcb8b),
+        blue: rgb(0x81a1c1),
+        magenta: rgb(0xb48ead),
+        cyan: rgb(0x88c0d0),
+        white: rgb(0xe5e9f0),
+    },
+    Palette {
+        name: "gruvbox-dark",
+        background: rgb(0x282828),
+        foreground: rgb(0xebdbb2),
+        black: rgb(0x3c3836),
+        red: rgb(0xfb4934),
+        green: rgb(0xb8bb26),
+        yellow: rgb(0xfabd2f),
+        blue: rgb(0x83a598),
+        magenta: rgb(0xd3869b),
+        cyan: rgb(0x8ec07c),
+        white: rgb(0xd5c4a1),
+    },
+    Palette {
+        name: "gruvbox-light",
+        background: rgb(0xfbf1c7),
+        foreground: rgb(0x3c3836),
+        black: rgb(0x282828),
+        red: rgb(0x9d0006),
+        green: rgb(0x79740e),
+        yellow: rgb(0xb57614),
+        blue: rgb(0x076678),
+        magenta: rgb(0x8f3f71),
+        cyan: rgb(0x427b58),
+        white: rgb(0x7c6f64),
+    },
+    Palette {
+        name: "tokyo-night",
+        background: tokyo_night::BG,
+        foreground: tokyo_night::FG,
+        black: tokyo_night::BLACK,
+        red: tokyo_night::RED,
+        green: tokyo_night::GREEN,
+        yellow: tokyo_night::YELLOW,
+        blue: tokyo_night::BLUE,
+        magenta: tokyo_night::MAGENTA,
+        cyan: tokyo_night::CYAN,
+        white: tokyo_night::FG_DARK,
+    },
+    Palette {
+        name: "dracula",
+        background: rgb(0x282a36),
+        foreground: rgb(0xf8f8f2),
+        black: rgb(0x21222c),
+        red: rgb(0xff5555),
+        green: rgb(0x50fa7b),
+        yellow: rgb(0xf1fa8c),
+        blue: rgb(0xbd93f9),
+        magenta: rgb(0xff79c6),
+        cyan: rgb(0x8be9fd),
+        white: rgb(0xf8f8f2),
+    },
+    Palette {
+        name: "solarized-dark",
+        background: rgb(0x002b36),
+        foreground: rgb(0x839496),
+        black: rgb(0x073642),
+        red: rgb(0xdc322f),
+        green: rgb(0x859900),
+        yellow: rgb(0xb58900),
+        blue: rgb(0x268bd2),
+        magenta: rgb(0xd33682),
+        cyan: rgb(0x2aa198),
+        white: rgb(0xeee8d5),
+    },
+    Palette {
+        name: "solarized-light",
+        background: rgb(0xfdf6e3),
+        foreground: rgb(0x657b83),
+        black: rgb(0x073642),
+        red: rgb(0xdc322f),
+        green: rgb(0x859900),
+        yellow: rgb(0xb58900),
+        blue: rgb(0x268bd2),
+        magenta: rgb(0xd33682),
+        cyan: rgb(0x2aa198),
+        white: rgb(0xeee8d5),
+    },
+];
+
+/// Palette used when neither the config nor the theme picks one,
+/// matching the default Tokyo Night colors
+pub const DEFAULT_PALETTE: &str = "tokyo-night";
+
+impl Palette {
+    /// Look up a built-in palette by name (case-insensitive, `_` or `-`)
+    pub fn find(name: &str) -> Option<&'static Palette> {
+        let name = name.trim().to_lowercase().replace('_', "-");
+        PALETTES.iter().find(|p| p.name == name)
+    }
+
+    /// Look up a palette by name, or the default palette when `name` is None
+    pub fn select(name: Option<&str>) -> Result<&'static Palette> {
+        let name = name.unwrap_or(DEFAULT_PALETTE);
+        Self::find(name).ok_or_else(|| {
+            anyhow!(
+                "Unknown palette '{}'. Available: {}",
+                name,
+                Self::names().join(", ")
+            )
+        })
+    }
+
+    /// Names of all built-in palettes, for error messages
+    pub fn names() -> Vec<&'static str> {
+        PALETTES.iter().map(|p| p.name).collect()
+    }
+
+    /// Colors keyed as `palette.<role>`, for resolving names in style tags
+    pub fn color_names(&self) -> impl Iterator<Item = (String, String)> {
+        self.entries()
+            .into_iter()
+            .map(|(role, color)| (format!("palette.{}", role), color.to_hex()))
+    }
+}
+
+impl ColorScheme for Palette {
+    fn background(&self) -> Color {
+        self.background
+    }
+    fn foreground(&self) -> Color {
+        self.foreground
+    }
+    fn black(&self) -> Color {
+        self.black
+    }
+    fn red(&self) -> Color {
+        self.red
+    }
+    fn green(&self) -> Color {
+        self.green
+    }
+    fn yellow(&self) -> Color {
+        self.yellow
+    }
+    fn blue(&self) -> Color {
+        self.blue
+    }
+    fn magenta(&self) -> Color {
+        self.magenta
+    }
+    fn cyan(&self) -> Color {
+        self.cyan
+    }
+    fn white(&self) -> Color {
+        self.white
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_palette() {
+        let mocha = Palette::find("catppuccin-mocha").unwrap();
+        assert_eq!(mocha.red().to_hex(), "#f38ba8");
+        assert_eq!(Palette::find("Catppuccin_Mocha"), Some(mocha));
+        assert!(Palette::find("nope").is_none());
+
+        let tokyo = Palette::select(None).unwrap();
+        assert_eq!(tokyo.background(), tokyo_night::BG);
+
+        let err = Palette::select(Some("nope")).unwrap_err().to_string();
+        assert!(err.contains("Unknown palette 'nope'"));
+        assert!(err.contains("dracula"));
+    }
+
+    #[test]
+    fn test_palette_entries() {
+        let nord = Palette::find("nord").unwrap();
+        let entries = nord.entries();
+        assert_eq!(entries.len(), 10);
+        assert!(entries.contains(&("cyan", rgb(0x88c0d0))));
+        assert!(entries.contains(&("background", rgb(0x2e3440))));
+
+        let names: Vec<(String, String)> = nord.color_names().collect();
+        assert!(names.contains(&("palette.red".to_string(), "#bf616a".to_string())));
+    }
+}
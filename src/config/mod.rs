@@ -119,8 +119,11 @@ impl Default for SymbolConfig {
 #[allow(dead_code)]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SegmentConfig {
+    #[serde(default)]
     pub left: Vec<String>,
+    #[serde(default)]
     pub center: Vec<String>,
+    #[serde(default)]
     pub right: Vec<String>,
 }
 
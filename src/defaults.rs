@@ -6,6 +6,7 @@
 //! to eliminate duplication between main.rs and config/mod.rs
 
 use crate::color::tokyo_night;
+use crate::symbols::{self, SymbolSet};
 use serde_json::{json, Value};
 use std::collections::HashMap;
 
@@ -55,58 +56,52 @@ pub fn default_colors_string() -> HashMap<String, String> {
     colors
 }
 
+/// Default symbol names and the built-in symbol each one shows, so they
+/// follow the active symbol set like `@name` does
+const DEFAULT_SYMBOLS: &[(&str, &str)] = &[
+    ("prompt_arrow", "prompt_arrow"),
+    ("segment_separator", "triangle_right"),
+    ("segment_separator_thin", "angle_right"),
+    ("git_branch", "git_branch"),
+    ("git_dirty", "git_dirty"),
+    ("git_clean", "git_clean"),
+    ("ssh", "ssh"),
+    ("root", "root"),
+    ("jobs", "cog"),
+    ("error", "error"),
+    ("success", "success"),
+    ("folder", "folder"),
+    ("home", "home"),
+    ("python", "python"),
+    ("node", "node"),
+    ("rust", "rust"),
+    ("docker", "docker"),
+    ("k8s", "k8s"),
+    ("aws", "aws"),
+];
+
 /// Get default symbols as a HashMap<String, Value> for template context
 pub fn default_symbols_json() -> HashMap<String, Value> {
-    let mut symbols = HashMap::new();
-
-    symbols.insert("prompt_arrow".to_string(), json!("❯"));
-    symbols.insert("segment_separator".to_string(), json!(""));
-    symbols.insert("segment_separator_thin".to_string(), json!(""));
-    symbols.insert("git_branch".to_string(), json!(""));
-    symbols.insert("git_dirty".to_string(), json!("✗"));
-    symbols.insert("git_clean".to_string(), json!("✓"));
-    symbols.insert("ssh".to_string(), json!(""));
-    symbols.insert("root".to_string(), json!(""));
-    symbols.insert("jobs".to_string(), json!(""));
-    symbols.insert("error".to_string(), json!("✖"));
-    symbols.insert("success".to_string(), json!("✓"));
-    symbols.insert("folder".to_string(), json!(""));
-    symbols.insert("home".to_string(), json!(""));
-    symbols.insert("python".to_string(), json!("🐍"));
-    symbols.insert("node".to_string(), json!(""));
-    symbols.insert("rust".to_string(), json!("🦀"));
-    symbols.insert("docker".to_string(), json!("🐳"));
-    symbols.insert("k8s".to_string(), json!("☸"));
-    symbols.insert("aws".to_string(), json!("☁"));
-
-    symbols
+    default_symbols_string()
+        .into_iter()
+        .map(|(name, symbol)| (name, json!(symbol)))
+        .collect()
 }
 
 /// Get default symbols as a HashMap<String, String> for preprocessing
 pub fn default_symbols_string() -> HashMap<String, String> {
-    let mut symbols = HashMap::new();
-
-    symbols.insert("prompt_arrow".to_string(), "❯".to_string());
-    symbols.insert("segment_separator".to_string(), "".to_string());
-    symbols.insert("segment_separator_thin".to_string(), "".to_string());
-    symbols.insert("git_branch".to_string(), "".to_string());
-    symbols.insert("git_dirty".to_string(), "✗".to_string());
-    symbols.insert("git_clean".to_string(), "✓".to_string());
-    symbols.insert("ssh".to_string(), "".to_string());
-    symbols.insert("root".to_string(), "".to_string());
-    symbols.insert("jobs".to_string(), "".to_string());
-    symbols.insert("error".to_string(), "✖".to_string());
-    symbols.insert("success".to_string(), "✓".to_string());
-    symbols.insert("folder".to_string(), "".to_string());
-    symbols.insert("home".to_string(), "".to_string());
-    symbols.insert("python".to_string(), "🐍".to_string());
-    symbols.insert("node".to_string(), "".to_string());
-    symbols.insert("rust".to_string(), "🦀".to_string());
-    symbols.insert("docker".to_string(), "🐳".to_string());
-    symbols.insert("k8s".to_string(), "☸".to_string());
-    symbols.insert("aws".to_string(), "☁".to_string());
-
-    symbols
+    default_symbols_in(symbols::active_set())
+}
+
+/// Default symbols in a specific symbol set
+fn default_symbols_in(set: SymbolSet) -> HashMap<String, String> {
+    DEFAULT_SYMBOLS
+        .iter()
+        .filter_map(|(name, builtin)| {
+            let symbol = symbols::resolve_builtin_in(builtin, set)?;
+            Some((name.to_string(), symbol.to_string()))
+        })
+        .collect()
 }
 
 #[cfg(test)]
@@ -128,6 +123,29 @@ mod tests {
         assert!(symbols.contains_key("git_branch"));
     }
 
+    #[test]
+    fn test_default_symbols_follow_set() {
+        // Every default resolves to a built-in symbol
+        assert_eq!(
+            default_symbols_in(SymbolSet::Nerd).len(),
+            DEFAULT_SYMBOLS.len()
+        );
+
+        std::env::set_var("ZUSH_SYMBOLS", "ascii");
+        let set = symbols::requested_set(None).unwrap().unwrap_or_default();
+        std::env::remove_var("ZUSH_SYMBOLS");
+        assert_eq!(set, SymbolSet::Ascii);
+
+        let ascii = default_symbols_in(set);
+        assert_eq!(ascii["prompt_arrow"], ">");
+        assert_eq!(ascii["error"], "x");
+        assert!(
+            ascii.values().all(|symbol| symbol.is_ascii()),
+            "{:?}",
+            ascii
+        );
+    }
+
     #[test]
     fn test_colors_are_valid_hex() {
         let colors = default_colors_string();
@@ -0,0 +1,375 @@
+//! Human-readable formatting for durations, counts, byte sizes and times
+//!
+//! Shared by the template helpers (`format_duration`, `format_number`,
+//! `format_bytes`, `format_time`) and the history views, so both render
+//! values the same way.
+
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use std::str::FromStr;
+
+/// Display style for durations
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DurationStyle {
+    /// Minutes and seconds without spaces: `850ms`, `2.5s`, `62m3s`
+    #[default]
+    Compact,
+    /// Zero-padded units separated by spaces: `1h 02m 03s`
+    Hms,
+    /// A single decimal value in the largest unit: `1.2s`, `4.5m`, `1.2h`
+    Short,
+}
+
+impl FromStr for DurationStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "compact" => Ok(DurationStyle::Compact),
+            "hms" | "long" | "clock" => Ok(DurationStyle::Hms),
+            "short" | "decimal" => Ok(DurationStyle::Short),
+            _ => Err(format!("unknown duration style '{}'", s)),
+        }
+    }
+}
+
+impl DurationStyle {
+    /// Decimal places used when no precision is given
+    pub fn default_precision(self) -> usize {
+        match self {
+            DurationStyle::Hms => 0,
+            DurationStyle::Compact | DurationStyle::Short => 1,
+        }
+    }
+}
+
+/// Byte size unit system
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ByteUnits {
+    /// Powers of 1000: kB, MB, GB
+    #[default]
+    Si,
+    /// Powers of 1024: KiB, MiB, GiB
+    Iec,
+}
+
+impl FromStr for ByteUnits {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "si" | "decimal" => Ok(ByteUnits::Si),
+            "iec" | "binary" => Ok(ByteUnits::Iec),
+            _ => Err(format!("unknown byte units '{}'", s)),
+        }
+    }
+}
+
+/// Format a duration given in milliseconds
+/// `precision` controls the decimals of the smallest displayed unit when it
+/// can be fractional (seconds under a minute, or the single unit in `Short`)
+pub fn format_duration(ms: f64, style: DurationStyle, precision: usize) -> String {
+    // Round before picking the unit, so 59.99s shows as a minute, not "60.0s"
+    let ms = ms.max(0.0).round();
+
+    if ms < 1000.0 {
+        return format!("{}ms", ms as u64);
+    }
+
+    let secs = ms / 1000.0;
+    match style {
+        DurationStyle::Compact | DurationStyle::Hms if round_to(secs, precision) < 60.0 => {
+            format!("{:.*}s", precision, secs)
+        }
+        DurationStyle::Compact => {
+            let total = secs.round() as u64;
+            format!("{}m{}s", total / 60, total % 60)
+        }
+        DurationStyle::Hms => {
+            let total = secs.round() as u64;
+            let (hours, mins, secs) = (total / 3600, (total % 3600) / 60, total % 60);
+            if hours > 0 {
+                format!("{}h {:02}m {:02}s", hours, mins, secs)
+            } else {
+                format!("{}m {:02}s", mins, secs)
+            }
+        }
+        DurationStyle::Short => {
+            const UNITS: [(f64, &str, f64); 4] = [
+                (1.0, "s", 60.0),
+                (60.0, "m", 60.0),
+                (3600.0, "h", 24.0),
+                (86_400.0, "d", f64::INFINITY),
+            ];
+            let (size, suffix) = UNITS
+                .iter()
+                .find(|(size, _, limit)| round_to(secs / size, precision) < *limit)
+                .map(|(size, suffix, _)| (*size, *suffix))
+                .unwrap_or((86_400.0, "d"));
+            format!("{:.*}{}", precision, secs / size, suffix)
+        }
+    }
+}
+
+/// Format a number with thousands separators: `1234567` -> `1,234,567`
+pub fn format_grouped(value: f64, precision: usize) -> String {
+    let formatted = format!("{:.*}", precision, value.abs());
+    let (int_part, frac_part) = match formatted.split_once('.') {
+        Some((i, f)) => (i, Some(f)),
+        None => (formatted.as_str(), None),
+    };
+
+    let mut grouped = String::new();
+    for (i, ch) in int_part.chars().enumerate() {
+        if i > 0 && (int_part.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(ch);
+    }
+
+    // Avoid "-0" when a small negative value rounds away
+    let sign = if value < 0.0 && formatted.bytes().any(|b| matches!(b, b'1'..=b'9')) {
+        "-"
+    } else {
+        ""
+    };
+
+    match frac_part {
+        Some(frac) => format!("{}{}.{}", sign, grouped, frac),
+        None => format!("{}{}", sign, grouped),
+    }
+}
+
+/// Format a number with an SI suffix: `1234` -> `1.2k`, `2500000` -> `2.5M`
+pub fn format_si(value: f64, precision: usize) -> String {
+    const SUFFIXES: [&str; 6] = ["", "k", "M", "G", "T", "P"];
+    let (scaled, idx) = scale(value, 1000.0, SUFFIXES.len(), precision);
+    if idx == 0 {
+        format_plain(value, precision)
+    } else {
+        format!("{:.*}{}", precision, scaled, SUFFIXES[idx])
+    }
+}
+
+/// Format a byte count: `1536` -> `1.5 kB` (SI) or `1.5 KiB` (IEC)
+pub fn format_bytes(bytes: f64, units: ByteUnits, precision: usize) -> String {
+    let (base, suffixes): (f64, [&str; 6]) = match units {
+        ByteUnits::Si => (1000.0, ["B", "kB", "MB", "GB", "TB", "PB"]),
+        ByteUnits::Iec => (1024.0, ["B", "KiB", "MiB", "GiB", "TiB", "PiB"]),
+    };
+    let (scaled, idx) = scale(bytes.round(), base, suffixes.len(), precision);
+    if idx == 0 {
+        format!("{} B", bytes.round() as i64)
+    } else {
+        format!("{:.*} {}", precision, scaled, suffixes[idx])
+    }
+}
+
+/// Format an instant with a chrono strftime string in the given time zone
+/// `zone` is "local" (or empty), "UTC", or an IANA name like "America/New_York".
+/// Returns None for an unknown zone or an invalid format string.
+pub fn format_time(instant: DateTime<Utc>, zone: &str, fmt: &str) -> Option<String> {
+    let items: Vec<Item> = StrftimeItems::new(fmt).collect();
+    if items.iter().any(|item| matches!(item, Item::Error)) {
+        return None;
+    }
+
+    let formatted = match zone.trim() {
+        "" | "local" | "Local" => instant
+            .with_timezone(&Local)
+            .format_with_items(items.iter())
+            .to_string(),
+        "UTC" | "utc" | "Z" => instant.format_with_items(items.iter()).to_string(),
+        name => {
+            let tz: Tz = name.parse().ok()?;
+            instant
+                .with_timezone(&tz)
+                .format_with_items(items.iter())
+                .to_string()
+        }
+    };
+
+    Some(formatted)
+}
+
+/// Whether `zone` names a time zone understood by `format_time`
+pub fn is_time_zone(zone: &str) -> bool {
+    matches!(zone.trim(), "local" | "Local" | "UTC" | "utc" | "Z") || zone.parse::<Tz>().is_ok()
+}
+
+/// Convert a Unix timestamp in (possibly fractional) seconds to an instant
+pub fn instant_from_epoch(secs: f64) -> Option<DateTime<Utc>> {
+    let whole = secs.floor();
+    let nanos = ((secs - whole) * 1e9) as u32;
+    Utc.timestamp_opt(whole as i64, nanos).single()
+}
+
+/// Parse a timestamp given as text: Unix seconds, RFC 3339, or a local
+/// wall-clock time today such as the `time` variable ("14:03:22")
+pub fn parse_timestamp(s: &str) -> Option<DateTime<Utc>> {
+    let s = s.trim();
+    if let Ok(secs) = s.parse::<f64>() {
+        return instant_from_epoch(secs);
+    }
+    if let Ok(instant) = DateTime::parse_from_rfc3339(s) {
+        return Some(instant.with_timezone(&Utc));
+    }
+    let time = NaiveTime::parse_from_str(s, "%H:%M:%S")
+        .or_else(|_| NaiveTime::parse_from_str(s, "%H:%M"))
+        .ok()?;
+    Local::now()
+        .date_naive()
+        .and_time(time)
+        .and_local_timezone(Local)
+        .earliest()
+        .map(|instant| instant.with_timezone(&Utc))
+}
+
+/// Divide by `base` until the value, rounded to `precision`, fits the unit,
+/// returning (scaled, unit index)
+fn scale(value: f64, base: f64, units: usize, precision: usize) -> (f64, usize) {
+    let mut scaled = value;
+    let mut idx = 0;
+    while round_to(scaled, precision).abs() >= base && idx + 1 < units {
+        scaled /= base;
+        idx += 1;
+    }
+    (scaled, idx)
+}
+
+/// Round to `precision` decimals, the way `{:.*}` would print it
+fn round_to(value: f64, precision: usize) -> f64 {
+    let factor = 10f64.powi(precision as i32);
+    (value * factor).round() / factor
+}
+
+/// Whole numbers print without decimals, fractional ones use `precision`
+fn format_plain(value: f64, precision: usize) -> String {
+    if value.fract() == 0.0 {
+        format!("{}", value as i64)
+    } else {
+        format!("{:.*}", precision, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_duration_compact() {
+        let style = DurationStyle::Compact;
+        assert_eq!(format_duration(500.0, style, 1), "500ms");
+        assert_eq!(format_duration(2500.0, style, 1), "2.5s");
+        assert_eq!(format_duration(12345.6789, style, 2), "12.35s");
+        assert_eq!(format_duration(125_000.0, style, 1), "2m5s");
+        assert_eq!(format_duration(3_723_000.0, style, 1), "62m3s");
+    }
+
+    #[test]
+    fn test_duration_hms() {
+        let style = DurationStyle::Hms;
+        assert_eq!(format_duration(3_723_000.0, style, 0), "1h 02m 03s");
+        assert_eq!(format_duration(125_000.0, style, 0), "2m 05s");
+        assert_eq!(format_duration(45_400.0, style, 0), "45s");
+        assert_eq!(format_duration(45_400.0, style, 1), "45.4s");
+    }
+
+    #[test]
+    fn test_duration_short() {
+        let style = DurationStyle::Short;
+        assert_eq!(format_duration(1234.0, style, 1), "1.2s");
+        assert_eq!(format_duration(270_000.0, style, 1), "4.5m");
+        assert_eq!(format_duration(4_320_000.0, style, 1), "1.2h");
+        assert_eq!(format_duration(172_800_000.0, style, 0), "2d");
+    }
+
+    #[test]
+    fn test_duration_style_from_str() {
+        assert_eq!("hms".parse(), Ok(DurationStyle::Hms));
+        assert_eq!("SHORT".parse(), Ok(DurationStyle::Short));
+        assert!("unknown".parse::<DurationStyle>().is_err());
+        assert_eq!("iec".parse(), Ok(ByteUnits::Iec));
+        assert!("furlongs".parse::<ByteUnits>().is_err());
+    }
+
+    #[test]
+    fn test_unit_boundaries() {
+        assert_eq!(format_duration(999.6, DurationStyle::Compact, 1), "1.0s");
+        assert_eq!(format_duration(59_999.0, DurationStyle::Compact, 1), "1m0s");
+        assert_eq!(format_duration(59_999.0, DurationStyle::Hms, 0), "1m 00s");
+        assert_eq!(format_duration(59_960.0, DurationStyle::Short, 1), "1.0m");
+        assert_eq!(format_duration(3_599_000.0, DurationStyle::Short, 0), "1h");
+        assert_eq!(format_si(999_999.0, 1), "1.0M");
+        assert_eq!(format_si(999.0, 1), "999");
+        assert_eq!(format_bytes(1_023_999.0, ByteUnits::Iec, 0), "1000 KiB");
+        assert_eq!(format_bytes(1_048_570.0, ByteUnits::Iec, 1), "1.0 MiB");
+    }
+
+    #[test]
+    fn test_format_grouped() {
+        assert_eq!(format_grouped(0.0, 0), "0");
+        assert_eq!(format_grouped(999.0, 0), "999");
+        assert_eq!(format_grouped(1_234_567.0, 0), "1,234,567");
+        assert_eq!(format_grouped(-1234.5, 1), "-1,234.5");
+    }
+
+    #[test]
+    fn test_format_si() {
+        assert_eq!(format_si(999.0, 1), "999");
+        assert_eq!(format_si(1234.0, 1), "1.2k");
+        assert_eq!(format_si(2_500_000.0, 1), "2.5M");
+        assert_eq!(format_si(-4200.0, 0), "-4k");
+    }
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(512.0, ByteUnits::Si, 1), "512 B");
+        assert_eq!(format_bytes(1536.0, ByteUnits::Si, 1), "1.5 kB");
+        assert_eq!(format_bytes(1536.0, ByteUnits::Iec, 1), "1.5 KiB");
+        assert_eq!(
+            format_bytes(5.0 * 1024.0 * 1024.0, ByteUnits::Iec, 0),
+            "5 MiB"
+        );
+        assert_eq!(format_bytes(3_000_000_000.0, ByteUnits::Si, 2), "3.00 GB");
+    }
+
+    #[test]
+    fn test_format_time_zones() {
+        let instant = instant_from_epoch(1_700_000_000.0).unwrap();
+        assert_eq!(
+            format_time(instant, "UTC", "%Y-%m-%d %H:%M:%S").unwrap(),
+            "2023-11-14 22:13:20"
+        );
+        assert_eq!(
+            format_time(instant, "America/New_York", "%H:%M %Z").unwrap(),
+            "17:13 EST"
+        );
+        assert_eq!(
+            format_time(instant, "Asia/Tokyo", "%a %I:%M %p").unwrap(),
+            "Wed 07:13 AM"
+        );
+        assert!(format_time(instant, "local", "%H:%M").is_some());
+    }
+
+    #[test]
+    fn test_parse_timestamp() {
+        let instant = instant_from_epoch(1_700_000_000.0).unwrap();
+        assert_eq!(parse_timestamp("1700000000"), Some(instant));
+        assert_eq!(parse_timestamp("2023-11-14T22:13:20Z"), Some(instant));
+        let today = parse_timestamp("14:03:22").unwrap();
+        assert_eq!(format_time(today, "local", "%H:%M:%S").unwrap(), "14:03:22");
+        assert_eq!(parse_timestamp("Europe/Nowhere"), None);
+    }
+
+    #[test]
+    fn test_format_time_invalid() {
+        let instant = instant_from_epoch(0.0).unwrap();
+        assert!(format_time(instant, "Not/AZone", "%H").is_none());
+        assert!(format_time(instant, "UTC", "%Q").is_none());
+        assert!(is_time_zone("Europe/Berlin"));
+        assert!(is_time_zone("UTC"));
+        assert!(!is_time_zone("14:03:22"));
+    }
+}
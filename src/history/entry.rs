@@ -1,5 +1,6 @@
 //! History entry type and serialization
 
+use crate::format::DurationStyle;
 use serde::{Deserialize, Serialize};
 
 /// A single history entry with full metadata
@@ -64,15 +65,13 @@ impl HistoryEntry {
 
     /// Get formatted duration for display
     pub fn formatted_duration(&self) -> String {
-        if self.dur < 1000 {
-            format!("{}ms", self.dur)
-        } else if self.dur < 60_000 {
-            format!("{:.1}s", self.dur as f64 / 1000.0)
+        // History has always truncated to whole seconds past a minute
+        let dur = if self.dur < 60_000 {
+            self.dur
         } else {
-            let mins = self.dur / 60_000;
-            let secs = (self.dur % 60_000) / 1000;
-            format!("{}m{}s", mins, secs)
-        }
+            self.dur / 1000 * 1000
+        };
+        crate::format::format_duration(dur as f64, DurationStyle::Compact, 1)
     }
 
     /// Get shortened directory for display
@@ -125,5 +124,8 @@ mod tests {
 
         entry.dur = 125_000;
         assert_eq!(entry.formatted_duration(), "2m5s");
+
+        entry.dur = 125_600;
+        assert_eq!(entry.formatted_duration(), "2m5s");
     }
 }
@@ -523,9 +523,10 @@ zush_prompt() {
     if [[ -f "$ZUSH_SIGNAL_FILE" ]]; then
         rm -f "$ZUSH_SIGNAL_FILE"
     else
-        # Check if a background worker is running by looking for lock files
+        # Check if a background worker is running by looking for lock files,
+        # or a watcher still has to signal an update (pending files)
         local cache_dir="${${XDG_CACHE_HOME:-$HOME/.cache}/zush}"
-        local lock_files=( "$cache_dir"/git-status-*.lock(N) )
+        local lock_files=( "$cache_dir"/git-status-*.lock(N) "$cache_dir"/git-status-*.pending(N) )
         if (( ${#lock_files} )); then
             _zush_async_start
         fi
@@ -0,0 +1,282 @@
+//! Jujutsu (jj) working copy detection
+//!
+//! A `.jj` directory marks a jj workspace, usually colocated with a git repo.
+//! Detection is a few `stat` calls. Its store is protobuf, so everything past
+//! detection needs a `jj log -r @` call, which only runs with `ZUSH_JJ_LOG=1`
+//! and is killed after `ZUSH_JJ_TIMEOUT` milliseconds. The call runs with
+//! `--ignore-working-copy`, so it never snapshots or takes locks; its output
+//! is cached until the next jj operation, and `jj_empty` and `jj_conflict`
+//! reflect the last jj command.
+
+use serde_json::{json, Value};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use crate::git::{self, is_env_truthy};
+
+/// One line per field; a description's first line can't contain a newline
+const LOG_TEMPLATE: &str = r#"change_id.shortest(8) ++ "\n" ++ local_bookmarks.map(|b| b.name()).join(" ") ++ "\n" ++ if(conflict, "1") ++ "\n" ++ if(empty, "1") ++ "\n" ++ description.first_line()"#;
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct JjStatus {
+    /// Root of the workspace (the directory containing `.jj`)
+    pub root: PathBuf,
+    /// Shortest unique prefix of the working-copy change id (at least 8 chars)
+    pub change_id: String,
+    /// First line of the working-copy commit description
+    pub description: String,
+    /// Local bookmarks pointing at the working-copy commit
+    pub bookmarks: Vec<String>,
+    /// The working-copy commit has unresolved conflicts
+    pub conflict: bool,
+    /// The working-copy commit has no changes
+    pub empty: bool,
+}
+
+impl JjStatus {
+    /// What to show where the git segment would show a branch: the first
+    /// bookmark, or the change id for an unnamed change
+    pub fn branch_label(&self) -> &str {
+        self.bookmarks
+            .first()
+            .map(String::as_str)
+            .unwrap_or(&self.change_id)
+    }
+}
+
+/// Nearest directory at or above `path` containing a `.jj` workspace
+fn find_root(mut path: &Path) -> Option<PathBuf> {
+    loop {
+        // Secondary workspaces have `.jj/repo` as a file pointing at the main one
+        if path.join(".jj").join("repo").exists() {
+            return Some(path.to_path_buf());
+        }
+        path = path.parent()?;
+    }
+}
+
+/// Detect a jj workspace and read the working-copy change
+pub fn get_jj_status(path: &Path) -> Option<JjStatus> {
+    let root = find_root(path)?;
+    let mut status = JjStatus {
+        root,
+        ..Default::default()
+    };
+
+    if !is_env_truthy("ZUSH_JJ_LOG") {
+        return Some(status);
+    }
+
+    // Every jj command that changes anything records an operation, which
+    // replaces the op heads; until then the last output still holds
+    let key = git::stat_stamp(&repo_dir(&status.root).join("op_heads/heads"));
+    let cache_path = log_cache_path(&status.root);
+    if let Some(output) = read_log_cache(&cache_path, &key) {
+        parse_log(&output, &mut status);
+        return Some(status);
+    }
+
+    let timeout = std::env::var("ZUSH_JJ_TIMEOUT")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .map(Duration::from_millis)
+        .unwrap_or(Duration::from_millis(200));
+
+    let mut cmd = Command::new("jj");
+    cmd.current_dir(&status.root).args([
+        "--ignore-working-copy",
+        "--no-pager",
+        "--color",
+        "never",
+        "log",
+        "--no-graph",
+        "-r",
+        "@",
+        "-T",
+        LOG_TEMPLATE,
+    ]);
+    if let Some(output) = output_with_timeout(&mut cmd, timeout) {
+        parse_log(&output, &mut status);
+        let _ = write_log_cache(&cache_path, &key, &output);
+    }
+    Some(status)
+}
+
+/// The shared repo dir: `.jj/repo`, or where a secondary workspace's
+/// `.jj/repo` file points
+fn repo_dir(root: &Path) -> PathBuf {
+    let dot_jj = root.join(".jj");
+    match fs::read_to_string(dot_jj.join("repo")) {
+        Ok(target) => dot_jj.join(target.trim()),
+        Err(_) => dot_jj.join("repo"),
+    }
+}
+
+fn log_cache_path(root: &Path) -> PathBuf {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    let mut hasher = DefaultHasher::new();
+    root.hash(&mut hasher);
+    git::cache_dir().join(format!("jj-log-{:x}.json", hasher.finish()))
+}
+
+/// Cached `jj log` output when it was written for `key`
+fn read_log_cache(cache_path: &Path, key: &str) -> Option<String> {
+    let v: Value = serde_json::from_str(&fs::read_to_string(cache_path).ok()?).ok()?;
+    if v["key"].as_str() != Some(key) {
+        return None;
+    }
+    v["output"].as_str().map(str::to_string)
+}
+
+/// Write the `jj log` cache (atomic via temp + rename)
+fn write_log_cache(cache_path: &Path, key: &str, output: &str) -> std::io::Result<()> {
+    if let Some(parent) = cache_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let tmp_path = cache_path.with_extension("tmp");
+    fs::write(
+        &tmp_path,
+        json!({ "key": key, "output": output }).to_string(),
+    )?;
+    fs::rename(&tmp_path, cache_path)
+}
+
+/// Fill in the fields printed by [`LOG_TEMPLATE`]
+fn parse_log(output: &str, status: &mut JjStatus) {
+    let mut lines = output.lines();
+    status.change_id = lines.next().unwrap_or_default().trim().to_string();
+    status.bookmarks = lines
+        .next()
+        .unwrap_or_default()
+        .split_whitespace()
+        .map(str::to_string)
+        .collect();
+    status.conflict = lines.next() == Some("1");
+    status.empty = lines.next() == Some("1");
+    status.description = lines.next().unwrap_or_default().trim().to_string();
+}
+
+/// Run `cmd` and return its stdout, or None if it fails or takes longer than `timeout`
+fn output_with_timeout(cmd: &mut Command, timeout: Duration) -> Option<String> {
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+
+    let deadline = Instant::now() + timeout;
+    loop {
+        match child.try_wait().ok()? {
+            Some(exit) if exit.success() => break,
+            Some(_) => return None,
+            None if Instant::now() >= deadline => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return None;
+            }
+            None => std::thread::sleep(Duration::from_millis(2)),
+        }
+    }
+
+    // The output is a few short lines, so it fits in the pipe buffer
+    let mut output = String::new();
+    child.stdout.take()?.read_to_string(&mut output).ok()?;
+    Some(output)
+}
+
+/// Convert JjStatus to JSON for template context
+pub fn jj_status_to_json(status: &JjStatus) -> Value {
+    json!({
+        "jj_root": status.root.display().to_string(),
+        "jj_change_id": status.change_id,
+        "jj_description": status.description,
+        "jj_bookmarks": status.bookmarks.join(" "),
+        "jj_conflict": status.conflict,
+        "jj_empty": status.empty,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_find_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let nested = dir.path().join("src/deep");
+        fs::create_dir_all(&nested).unwrap();
+        assert_eq!(find_root(&nested), None);
+
+        fs::create_dir_all(dir.path().join(".jj/repo")).unwrap();
+        assert_eq!(find_root(&nested), Some(dir.path().to_path_buf()));
+    }
+
+    #[test]
+    fn test_log_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = dir.path().join("jj-log.json");
+        assert_eq!(read_log_cache(&cache, "op1"), None);
+        write_log_cache(&cache, "op1", "kxqpwmzt\n").unwrap();
+        assert_eq!(read_log_cache(&cache, "op1").as_deref(), Some("kxqpwmzt\n"));
+        assert_eq!(read_log_cache(&cache, "op2"), None);
+
+        // Secondary workspaces point at the main repo
+        let main = dir.path().join("main");
+        let second = dir.path().join("second");
+        fs::create_dir_all(main.join(".jj/repo")).unwrap();
+        fs::create_dir_all(second.join(".jj")).unwrap();
+        fs::write(
+            second.join(".jj/repo"),
+            main.join(".jj/repo").to_str().unwrap(),
+        )
+        .unwrap();
+        assert_eq!(repo_dir(&main), main.join(".jj/repo"));
+        assert_eq!(repo_dir(&second), main.join(".jj/repo"));
+    }
+
+    #[test]
+    fn test_parse_log() {
+        let mut status = JjStatus::default();
+        parse_log("kxqpwmzt\nmain feature\n1\n\nFix the parser\n", &mut status);
+        assert_eq!(status.change_id, "kxqpwmzt");
+        assert_eq!(status.bookmarks, ["main", "feature"]);
+        assert!(status.conflict);
+        assert!(!status.empty);
+        assert_eq!(status.description, "Fix the parser");
+        assert_eq!(status.branch_label(), "main");
+
+        // A fresh change: no bookmarks, no description
+        let mut status = JjStatus::default();
+        parse_log("yqosqzyt\n\n\n1\n", &mut status);
+        assert_eq!(status.branch_label(), "yqosqzyt");
+        assert!(status.empty);
+        assert_eq!(status.description, "");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_output_with_timeout() {
+        let mut echo = Command::new("echo");
+        echo.arg("hi");
+        assert_eq!(
+            output_with_timeout(&mut echo, Duration::from_secs(5)).as_deref(),
+            Some("hi\n")
+        );
+
+        let mut sleep = Command::new("sleep");
+        sleep.arg("5");
+        let start = Instant::now();
+        assert_eq!(
+            output_with_timeout(&mut sleep, Duration::from_millis(50)),
+            None
+        );
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+}
@@ -3,27 +3,32 @@ mod cli;
 mod color;
 mod config;
 mod defaults;
+mod format;
 mod git;
 #[cfg(feature = "history")]
 mod history;
 mod init;
+mod jj;
 mod modules;
 mod segments;
 mod symbols;
 mod template;
 mod toml_helpers;
+#[cfg(feature = "git-watcher")]
+mod watcher;
 
 use anyhow::{Context, Result};
 use clap::Parser;
 use serde_json::{json, Value};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::PathBuf;
 use std::sync::Mutex;
 use std::time::{Duration, Instant};
 
 use buffer::TerminalBuffer;
-use cli::{Cli, Commands};
+use cli::{Cli, Commands, ThemeCommands};
+use color::{ColorScheme, Palette};
 use template::TemplateEngine;
 
 /// Cache entry for config/theme files
@@ -100,6 +105,12 @@ fn main() -> Result<()> {
         Some(Commands::Completions { shell }) => {
             generate_completions(shell)?;
         }
+        Some(Commands::Theme { command }) => {
+            handle_theme_command(command)?;
+        }
+        Some(Commands::Bench { iterations }) => {
+            handle_bench(&cli, *iterations)?;
+        }
         Some(Commands::InternalGitStatus {
             repo_path,
             cache_path,
@@ -107,6 +118,18 @@ fn main() -> Result<()> {
         }) => {
             handle_internal_git_status(repo_path, cache_path, signal_file.as_deref())?;
         }
+        #[cfg(feature = "git-watcher")]
+        Some(Commands::InternalGitWatch {
+            repo_path,
+            cache_path,
+            signal_file,
+        }) => {
+            watcher::run(
+                std::path::Path::new(repo_path),
+                std::path::Path::new(cache_path),
+                signal_file.as_deref().map(std::path::Path::new),
+            )?;
+        }
         #[cfg(feature = "history")]
         Some(Commands::History { command }) => {
             handle_history_command(command)?;
@@ -146,6 +169,132 @@ fn generate_completions(shell: &str) -> Result<()> {
     Ok(())
 }
 
+/// Time loading the active theme and rendering `--template`, with and
+/// without the compiled theme cache
+fn handle_bench(cli: &Cli, iterations: u32) -> Result<()> {
+    let iterations = iterations.max(1);
+    let average = |use_cache: bool| -> Result<Duration> {
+        let start = Instant::now();
+        for _ in 0..iterations {
+            let (mut engine, compiled) = load_engine(cli, use_cache)?;
+            engine.set_context(HashMap::from([
+                ("colors".to_string(), json!(compiled.colors)),
+                ("symbols".to_string(), json!(compiled.symbols)),
+                ("palette".to_string(), json!(compiled.palette)),
+                ("user".to_string(), json!("user")),
+                ("pwd_short".to_string(), json!("~/projects")),
+                ("exit_code".to_string(), json!(0)),
+            ]));
+            // Strict themes may reject the sample context; only timing matters here
+            let _ = engine.render(&cli.template);
+        }
+        Ok(start.elapsed() / iterations)
+    };
+
+    let uncached = average(false)?;
+    // Populate the cache before timing cached loads
+    load_engine(cli, true)?;
+    let cached = average(true)?;
+
+    println!("Load + render '{}' over {} runs:", cli.template, iterations);
+    println!("  no cache: {:>8.3} ms", uncached.as_secs_f64() * 1000.0);
+    println!(
+        "  cached:   {:>8.3} ms ({:.1}x faster)",
+        cached.as_secs_f64() * 1000.0,
+        uncached.as_secs_f64() / cached.as_secs_f64().max(f64::EPSILON)
+    );
+    Ok(())
+}
+
+/// Handle theme development subcommands
+fn handle_theme_command(command: &ThemeCommands) -> Result<()> {
+    match command {
+        ThemeCommands::Check { file } => {
+            let contents = fs::read_to_string(file)
+                .with_context(|| format!("Failed to read theme: {:?}", file))?;
+            let parsed: toml::Value = toml::from_str(&contents)
+                .with_context(|| format!("Failed to parse theme: {:?}", file))?;
+            let templates: template::TemplateConfig = parsed.clone().try_into()?;
+            let toml_parser = toml_helpers::TomlParser::from_parsed(Some(parsed));
+
+            // Check against the symbols the prompt would render: ZUSH_SYMBOLS > theme
+            symbols::select_active_set(
+                toml_parser
+                    .get()
+                    .and_then(|t| t.get("symbol_set"))
+                    .and_then(|v| v.as_str()),
+            )?;
+
+            // Templates see default colors/symbols in context when the theme defines none
+            let mut color_names: HashSet<String> =
+                toml_parser.extract_colors().into_keys().collect();
+            if color_names.is_empty() {
+                color_names = defaults::default_colors_json().into_keys().collect();
+            }
+            let symbols = toml_parser.extract_symbols(parse_unicode_escapes);
+            let mut symbol_names: HashSet<String> = symbols.keys().cloned().collect();
+            if symbol_names.is_empty() {
+                symbol_names = defaults::default_symbols_json().into_keys().collect();
+            }
+
+            let palette_name = toml_parser
+                .get()
+                .and_then(|t| t.get("palette"))
+                .and_then(|v| v.as_str());
+            let mut colors = toml_parser.extract_colors();
+            colors.extend(Palette::select(palette_name)?.color_names());
+
+            let mut checker = template::ThemeChecker::new(
+                colors,
+                symbols,
+                toml_parser.extract_segments(),
+                color_names,
+                symbol_names,
+            )?;
+            checker.set_var_names(templates.vars.keys().cloned());
+
+            // [vars] entries are checked as templates named vars.<name>
+            let mut sources: Vec<(String, &String)> = templates
+                .templates
+                .iter()
+                .map(|(name, source)| (name.clone(), source))
+                .collect();
+            sources.sort();
+            sources.extend(
+                templates
+                    .vars
+                    .iter()
+                    .map(|(name, source)| (format!("vars.{}", name), source)),
+            );
+
+            let mut errors = 0;
+            let mut warnings = 0;
+            for (name, source) in &sources {
+                for diagnostic in checker.check_template(name, source) {
+                    match diagnostic.severity {
+                        template::Severity::Error => errors += 1,
+                        template::Severity::Warning => warnings += 1,
+                    }
+                    println!("{}: {}", file.display(), diagnostic);
+                }
+            }
+
+            println!(
+                "{}: {} template(s) checked, {} error(s), {} warning(s)",
+                file.display(),
+                sources.len(),
+                errors,
+                warnings
+            );
+            if errors > 0 {
+                std::process::exit(1);
+            }
+        }
+    }
+
+    Ok(())
+}
+
 /// Handle the internal git status background worker subcommand.
 /// This runs as a daemonized child process, computes git status via libgit2,
 /// writes the result to a cache file, and touches a signal file.
@@ -176,7 +325,8 @@ fn handle_internal_git_status(
     Ok(())
 }
 
-fn load_theme(theme_name: &str) -> Result<String> {
+/// Locate a theme file by name or path
+fn theme_path(theme_name: &str) -> Result<PathBuf> {
     // Check if it's a path to a custom theme
     let theme_path = if theme_name.contains('/') || theme_name.contains('.') {
         PathBuf::from(theme_name)
@@ -192,19 +342,16 @@ fn load_theme(theme_name: &str) -> Result<String> {
     };
 
     if theme_path.exists() {
-        // Use cached file read
-        read_file_cached(&theme_path)
+        Ok(theme_path)
     } else {
         Err(anyhow::anyhow!("Theme file not found: {:?}", theme_path))
     }
 }
 
-fn render_prompt(
-    cli: &Cli,
-    context_json: Option<&str>,
-    exit_code: Option<i32>,
-    execution_time: Option<f64>,
-) -> Result<()> {
+/// Build a template engine for the active theme and config
+/// With `use_cache`, a compiled theme from an earlier run is reused when the
+/// theme and config files are unchanged, skipping TOML parsing and preprocessing
+fn load_engine(cli: &Cli, use_cache: bool) -> Result<(TemplateEngine, template::CompiledTheme)> {
     // Load main configuration
     let config_path = cli.config.clone().or_else(|| {
         // Try .config first, then fall back to platform default
@@ -234,16 +381,16 @@ fn render_prompt(
 
     // Determine which theme to load
     // Priority: CLI flag > ZUSH_THEME env var > config file
-    let theme_str = if let Some(theme_name) = &cli.theme {
+    let theme_path = if let Some(theme_name) = &cli.theme {
         // CLI argument takes precedence
-        load_theme(theme_name).ok()
+        theme_path(theme_name).ok()
     } else if let Ok(theme_name) = std::env::var("ZUSH_THEME") {
         // Environment variable is second priority
-        load_theme(&theme_name).ok()
+        theme_path(&theme_name).ok()
     } else if let Some(ref parsed) = config_parsed {
         // Use already-parsed config to get theme name
         if let Some(theme_name) = parsed.get("theme").and_then(|v| v.as_str()) {
-            load_theme(theme_name).ok()
+            theme_path(theme_name).ok()
         } else {
             None
         }
@@ -251,6 +398,47 @@ fn render_prompt(
         None
     };
 
+    let cache_key = template::ThemeCacheKey::new(&[
+        theme_path.as_deref(),
+        config_str.as_ref().and(config_path.as_deref()),
+    ]);
+    if use_cache {
+        if let Some(compiled) = template::cache::load(&cache_key) {
+            let engine = TemplateEngine::from_compiled(&compiled)?;
+            return Ok((engine, compiled));
+        }
+    }
+
+    // Use cached file read
+    let theme_str = theme_path.and_then(|path| read_file_cached(&path).ok());
+    let (engine, compiled, clean) = compile_theme(cli, theme_str, config_str, config_parsed)?;
+
+    // Themes with errors are not cached, so the errors keep being reported
+    if use_cache && clean {
+        let _ = template::cache::store(&cache_key, &compiled);
+    }
+
+    Ok((engine, compiled))
+}
+
+/// Print a stylized error above the prompt (unless quiet mode)
+fn print_load_error(cli: &Cli, title: &str, error: &anyhow::Error) {
+    if !cli.quiet {
+        eprintln!("\n\x1b[38;2;243;139;168m\x1b[1m✖ {}\x1b[22m\x1b[39m", title);
+        eprintln!("\x1b[38;2;249;226;175m{:#}\x1b[39m\n", error);
+    }
+}
+
+/// Parse the theme and config, preprocess every template and resolve colors
+/// and symbols. Returns whether everything loaded without errors.
+fn compile_theme(
+    cli: &Cli,
+    theme_str: Option<String>,
+    config_str: Option<String>,
+    config_parsed: Option<toml::Value>,
+) -> Result<(TemplateEngine, template::CompiledTheme, bool)> {
+    let mut clean = true;
+
     // Create template engine
     let mut engine = TemplateEngine::new()?;
 
@@ -263,8 +451,78 @@ fn render_prompt(
         toml_helpers::TomlParser::from_parsed(config_parsed.clone())
     };
 
+    // Strict mode turns undefined variables into render errors (config overrides theme)
+    let strict = config_parsed
+        .as_ref()
+        .and_then(|c| c.get("strict"))
+        .or_else(|| toml_parser.get().and_then(|t| t.get("strict")))
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    engine.set_strict(strict);
+
+    // Environment variables readable by {{env}}, only ever granted by the user's config
+    let env_allow: Vec<String> = config_parsed
+        .as_ref()
+        .and_then(|c| c.get("env"))
+        .and_then(|env| env.get("allow"))
+        .and_then(|allow| allow.as_array())
+        .map(|allow| {
+            allow
+                .iter()
+                .filter_map(|v| v.as_str().map(str::to_string))
+                .collect()
+        })
+        .unwrap_or_default();
+    engine.set_env_allowlist(env_allow.clone());
+
+    // Directory substitutions for pwd_short and format_path (config overrides theme)
+    let path_substitutions: Vec<(String, String)> = config_parsed
+        .as_ref()
+        .and_then(|c| c.get("directory"))
+        .or_else(|| toml_parser.get().and_then(|t| t.get("directory")))
+        .and_then(|directory| directory.get("substitutions"))
+        .and_then(|substitutions| substitutions.as_table())
+        .map(|table| {
+            table
+                .iter()
+                .filter_map(|(key, label)| Some((key.clone(), label.as_str()?.to_string())))
+                .collect()
+        })
+        .unwrap_or_default();
+    match template::PathSubstitutions::new(&path_substitutions) {
+        Ok(substitutions) => engine.set_path_substitutions(substitutions),
+        Err(e) => {
+            print_load_error(cli, "Directory Substitution Error", &e);
+            clean = false;
+        }
+    }
+
+    // Symbol set for built-in symbols: ZUSH_SYMBOLS > config > theme
+    let symbol_set = config_parsed
+        .as_ref()
+        .and_then(|c| c.get("symbol_set"))
+        .or_else(|| toml_parser.get().and_then(|t| t.get("symbol_set")))
+        .and_then(|v| v.as_str());
+    if let Err(e) = symbols::select_active_set(symbol_set) {
+        print_load_error(cli, "Symbol Set Error", &e);
+        clean = false;
+    }
+
+    // Named palette exposed as palette.* (config overrides theme)
+    let palette_name = config_parsed
+        .as_ref()
+        .and_then(|c| c.get("palette"))
+        .or_else(|| toml_parser.get().and_then(|t| t.get("palette")))
+        .and_then(|v| v.as_str());
+    let palette = Palette::select(palette_name).or_else(|e| {
+        print_load_error(cli, "Palette Error", &e);
+        clean = false;
+        Palette::select(None)
+    })?;
+
     // Extract colors for preprocessing (allows templates to use named colors)
-    let colors_for_preprocessing = toml_parser.extract_colors();
+    let mut colors_for_preprocessing = toml_parser.extract_colors();
+    colors_for_preprocessing.extend(palette.color_names());
     engine.set_colors(colors_for_preprocessing);
 
     // Extract symbols for preprocessing (@symbol_name shortcuts)
@@ -283,12 +541,8 @@ fn render_prompt(
     if let Some(toml_str) = theme_or_config {
         if let Err(e) = engine.load_templates_from_config(toml_str) {
             // If loading fails, print stylized error (unless quiet mode) and register defaults
-            if !cli.quiet {
-                eprintln!(
-                    "\n\x1b[38;2;243;139;168m\x1b[1m✖ Template Loading Error\x1b[22m\x1b[39m"
-                );
-                eprintln!("\x1b[38;2;249;226;175m{}\x1b[39m\n", e);
-            }
+            print_load_error(cli, "Template Loading Error", &e);
+            clean = false;
             register_default_templates(&mut engine)?;
         }
     } else {
@@ -296,6 +550,66 @@ fn render_prompt(
         register_default_templates(&mut engine)?;
     }
 
+    // Declarative segment lists render into segments_left/center/right,
+    // which also serve as the left/right templates when a theme has none
+    if let Some(layout) = toml_parser.extract_segment_layout() {
+        if let Err(e) = engine.set_segment_layout(&layout) {
+            print_load_error(cli, "Segment Loading Error", &e);
+            clean = false;
+        }
+        for (template, variable) in [("left", "segments_left"), ("right", "segments_right")] {
+            if !engine.has_template(template) {
+                engine.register_template(template, &format!("{{{{{}}}}}", variable))?;
+            }
+        }
+    }
+
+    // Load colors and symbols from theme/config or use defaults
+    // Reuse the toml_parser we created earlier for preprocessing
+    let mut colors = toml_parser.extract_colors_as_json();
+    let mut symbols = toml_parser.extract_symbols_as_json(parse_unicode_escapes);
+
+    // Apply overrides from main config if theme was loaded
+    if theme_str.is_some() && config_str.is_some() {
+        let config_parser = toml_helpers::TomlParser::new(config_str.as_deref());
+        config_parser.apply_overrides(&mut colors, &mut symbols, parse_unicode_escapes);
+    }
+
+    // Use defaults if no colors/symbols were loaded from config
+    if colors.is_empty() {
+        colors = defaults::default_colors_json();
+    }
+    if symbols.is_empty() {
+        symbols = defaults::default_symbols_json();
+    }
+
+    let (templates, pipeline) = engine.compiled_parts();
+    let compiled = template::CompiledTheme {
+        strict,
+        templates,
+        pipeline,
+        colors,
+        symbols,
+        palette: palette
+            .entries()
+            .into_iter()
+            .map(|(role, color)| (role.to_string(), color.to_hex()))
+            .collect(),
+        env_allow,
+        path_substitutions,
+    };
+
+    Ok((engine, compiled, clean))
+}
+
+fn render_prompt(
+    cli: &Cli,
+    context_json: Option<&str>,
+    exit_code: Option<i32>,
+    execution_time: Option<f64>,
+) -> Result<()> {
+    let (mut engine, compiled) = load_engine(cli, !cli.no_cache)?;
+
     // Build context
     let mut context = HashMap::new();
 
@@ -389,6 +703,12 @@ fn render_prompt(
         }
     }
 
+    // Shorten pwd_short with [directory.substitutions]
+    if let Some(pwd_short) = context.get("pwd_short").and_then(|v| v.as_str()) {
+        let pwd_short = engine.substitute_path(pwd_short);
+        context.insert("pwd_short".to_string(), json!(pwd_short));
+    }
+
     // Detect if running over SSH
     let is_ssh = std::env::var("SSH_CONNECTION").is_ok() || std::env::var("SSH_TTY").is_ok();
     context.insert("is_ssh".to_string(), json!(is_ssh));
@@ -412,41 +732,52 @@ fn render_prompt(
 
     // Get git status natively (much faster than shell git commands)
     // This reads .git directory directly instead of spawning git processes
-    if let Some(pwd) = context.get("pwd").and_then(|v| v.as_str()) {
-        if let Some(git_status) = git::get_git_status(std::path::Path::new(pwd)) {
+    if let Some(pwd) = context
+        .get("pwd")
+        .and_then(|v| v.as_str())
+        .map(PathBuf::from)
+    {
+        // Detect jj first: it keeps a colocated git repo on a detached HEAD,
+        // so git's tag scan and describe would only be thrown away
+        let jj_status = jj::get_jj_status(&pwd);
+
+        if let Some(git_status) = git::get_git_status(&pwd, jj_status.is_none()) {
+            // Path below the work tree root, "" at the root itself
+            let pwd_in_repo = pwd
+                .strip_prefix(&git_status.root)
+                .map(|rel| rel.display().to_string())
+                .unwrap_or_default();
+            context.insert("pwd_in_repo".to_string(), json!(pwd_in_repo));
+
             let git_json = git::git_status_to_json(&git_status);
             if let Value::Object(git_map) = git_json {
                 for (key, value) in git_map {
                     context.insert(key, value);
                 }
             }
+            context.insert("vcs".to_string(), json!("git"));
+        }
+
+        if let Some(jj_status) = jj_status {
+            // jj leaves a colocated git repo on a detached HEAD all the time;
+            // show the bookmark or change instead of a bare commit id
+            let label = jj_status.branch_label();
+            if context.get("git_detached") == Some(&json!(true)) && !label.is_empty() {
+                context.insert("git_detached".to_string(), json!(false));
+                context.insert("git_branch".to_string(), json!(label));
+            }
+
+            if let Value::Object(jj_map) = jj::jj_status_to_json(&jj_status) {
+                for (key, value) in jj_map {
+                    context.insert(key, value);
+                }
+            }
+            context.insert("vcs".to_string(), json!("jj"));
         }
     }
 
-    // Ensure git status variables exist with defaults (if not in git repo)
-    context.entry("git_branch".to_string()).or_insert(json!(""));
-    context.entry("git_staged".to_string()).or_insert(json!(0));
-    context
-        .entry("git_modified".to_string())
-        .or_insert(json!(0));
-    context.entry("git_added".to_string()).or_insert(json!(0));
-    context.entry("git_deleted".to_string()).or_insert(json!(0));
-    context.entry("git_renamed".to_string()).or_insert(json!(0));
-    context
-        .entry("git_untracked".to_string())
-        .or_insert(json!(0));
-    context
-        .entry("git_conflicted".to_string())
-        .or_insert(json!(0));
-    context.entry("git_stash".to_string()).or_insert(json!(0));
-    context.entry("git_ahead".to_string()).or_insert(json!(0));
-    context.entry("git_behind".to_string()).or_insert(json!(0));
-    context
-        .entry("git_from_cache".to_string())
-        .or_insert(json!(false));
-    context
-        .entry("git_async_pending".to_string())
-        .or_insert(json!(false));
+    // Ensure git and jj variables exist with defaults (if not in a repo)
+    template::insert_context_defaults(&mut context);
 
     // Collect module information (Python, Node, Rust, Docker, etc.)
     // Skip auto-detection if modules were provided via context (e.g., for previews)
@@ -472,27 +803,12 @@ fn render_prompt(
         }
     }
 
-    // Load colors and symbols from theme/config or use defaults
-    // Reuse the toml_parser we created earlier for preprocessing
-    let mut colors = toml_parser.extract_colors_as_json();
-    let mut symbols = toml_parser.extract_symbols_as_json(parse_unicode_escapes);
+    // Keep `modules` defined (empty is falsy) so strict mode accepts {{#each modules}}
+    context.entry("modules".to_string()).or_insert(json!([]));
 
-    // Apply overrides from main config if theme was loaded
-    if theme_str.is_some() && config_str.is_some() {
-        let config_parser = toml_helpers::TomlParser::new(config_str.as_deref());
-        config_parser.apply_overrides(&mut colors, &mut symbols, parse_unicode_escapes);
-    }
-
-    // Use defaults if no colors/symbols were loaded from config
-    if colors.is_empty() {
-        colors = defaults::default_colors_json();
-    }
-    if symbols.is_empty() {
-        symbols = defaults::default_symbols_json();
-    }
-
-    context.insert("colors".to_string(), json!(colors));
-    context.insert("symbols".to_string(), json!(symbols));
+    context.insert("colors".to_string(), json!(compiled.colors));
+    context.insert("symbols".to_string(), json!(compiled.symbols));
+    context.insert("palette".to_string(), json!(compiled.palette));
 
     // Get terminal width directly from the terminal (not from shell)
     let terminal_width = if let Some((terminal_size::Width(w), _)) = terminal_size::terminal_size()
@@ -512,6 +828,31 @@ fn render_prompt(
     // Set context in engine
     engine.set_context(context.clone());
 
+    // Computed [vars] go in before anything else renders so every template can use them
+    match engine.render_vars() {
+        Ok(vars) => {
+            for (name, value) in vars {
+                context.insert(name, value);
+            }
+            engine.set_context(context.clone());
+        }
+        Err(e) => print_load_error(cli, "Variable Rendering Error", &e),
+    }
+
+    // Render segment lists now that the context is complete
+    for variable in ["segments_left", "segments_center", "segments_right"] {
+        context.insert(variable.to_string(), json!(""));
+    }
+    match engine.render_segment_lists() {
+        Ok(lists) => {
+            for (variable, output) in lists {
+                context.insert(variable, json!(output));
+            }
+        }
+        Err(e) => print_load_error(cli, "Segment Rendering Error", &e),
+    }
+    engine.set_context(context.clone());
+
     // Only build first_line for the main template (not for transient or other templates)
     if cli.template == "main" {
         // Pre-render left and right templates if they exist, and build complete first line in Rust
@@ -570,7 +911,7 @@ fn render_prompt(
         Err(e) => {
             // Display rendering error above the prompt
             eprintln!("\n\x1b[38;2;243;139;168m\x1b[1m✖ Template Rendering Error\x1b[22m\x1b[39m");
-            eprintln!("\x1b[38;2;249;226;175m{}\x1b[39m\n", e);
+            eprintln!("\x1b[38;2;249;226;175m{}: {}\x1b[39m\n", e, e.root_cause());
 
             // Fall back to a minimal safe prompt with user@host and directory
             // Get these from env variables since context was already moved
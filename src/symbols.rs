@@ -2,172 +2,289 @@
 //!
 //! This module provides a data-driven symbol registry instead of a large match statement,
 //! following the Open/Closed Principle - new symbols can be added without modifying code.
+//!
+//! Every symbol has a Nerd Font glyph plus plain Unicode and ASCII fallbacks for
+//! terminals without a patched font; the active [`SymbolSet`] picks between them.
 
+use anyhow::{bail, Result};
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicU8, Ordering};
 use std::sync::OnceLock;
 
-/// Get the global built-in symbols registry
-pub fn builtin_symbols() -> &'static HashMap<&'static str, &'static str> {
-    static SYMBOLS: OnceLock<HashMap<&'static str, &'static str>> = OnceLock::new();
-    SYMBOLS.get_or_init(|| {
-        let mut m = HashMap::with_capacity(100);
-
-        // Powerline triangles (solid arrows)
-        m.insert("triangle_right", "\u{e0b0}");
-        m.insert("tri_right", "\u{e0b0}");
-        m.insert("arrow_right", "\u{e0b0}");
-        m.insert("triangle_left", "\u{e0b2}");
-        m.insert("tri_left", "\u{e0b2}");
-        m.insert("arrow_left", "\u{e0b2}");
-
-        // Inverted triangles
-        m.insert("inverted_triangle_left", "\u{e0d7}");
-        m.insert("inv_tri_right", "\u{e0d7}");
-        m.insert("inv_arrow_right", "\u{e0d7}");
-        m.insert("inverted_triangle_right", "\u{e0d6}");
-        m.insert("inv_tri_left", "\u{e0d6}");
-        m.insert("inv_arrow_left", "\u{e0d6}");
-
-        // Powerline pills/rounded
-        m.insert("pill_left", "\u{e0b6}");
-        m.insert("round_left", "\u{e0b6}");
-        m.insert("pill_right", "\u{e0b4}");
-        m.insert("round_right", "\u{e0b4}");
-
-        // Flame
-        m.insert("flame_left", "\u{e0c0}");
-        m.insert("flame_right", "\u{e0c2}");
-
-        // Trapezoid shapes
-        m.insert("trapezoid_right", "\u{e0d2}");
-        m.insert("trapezoid_left", "\u{e0d4}");
-
-        // Powerline angles (thin arrows)
-        m.insert("angle_right", "\u{e0b1}");
-        m.insert("thin_right", "\u{e0b1}");
-        m.insert("angle_left", "\u{e0b3}");
-        m.insert("thin_left", "\u{e0b3}");
-
-        // Powerline thin pills/rounded
-        m.insert("pill_right_thin", "\u{e0b5}");
-        m.insert("round_right_thin", "\u{e0b5}");
-        m.insert("pill_left_thin", "\u{e0b7}");
-        m.insert("round_left_thin", "\u{e0b7}");
-
-        // Powerline circles (semi-circles)
-        m.insert("circle_right", "\u{e0b8}");
-        m.insert("semicircle_right", "\u{e0b8}");
-        m.insert("circle_left", "\u{e0ba}");
-        m.insert("semicircle_left", "\u{e0ba}");
-
-        // Powerline slants/diagonal
-        m.insert("slant_right", "\u{e0bc}");
-        m.insert("diagonal_right", "\u{e0bc}");
-        m.insert("slant_left", "\u{e0be}");
-        m.insert("diagonal_left", "\u{e0be}");
-
-        // Misc shapes
-        m.insert("ice_cream", "\u{f0efd}");
-        m.insert("ice_cream_thick", "\u{ef888}");
-        m.insert("ice_cream_outline", "\u{f082a}");
-
-        // Slash
-        m.insert("backslash", "\u{e216}");
-
-        // Additional powerline shapes
-        m.insert("lower_triangle_right", "\u{e0b8}");
-        m.insert("lower_triangle_left", "\u{e0ba}");
-        m.insert("upper_triangle_right", "\u{e0bc}");
-        m.insert("upper_triangle_left", "\u{e0be}");
-
-        // Common nerd font icons - Git
-        m.insert("git_branch", "\u{e0a0}");
-        m.insert("branch", "\u{e0a0}");
-        m.insert("lock", "\u{e0a2}");
-
-        // Common nerd font icons - UI
-        m.insert("cog", "\u{e615}");
-        m.insert("gear", "\u{e615}");
-        m.insert("home", "\u{f015}");
-        m.insert("folder", "\u{f07c}");
-        m.insert("folder_open", "\u{f07b}");
-
-        // Time & Status
-        m.insert("timer", "\u{f0109}");
-        m.insert("clock", "\u{f017}");
-        m.insert("calendar", "\u{f133}");
-        m.insert("check", "\u{f00c}");
-        m.insert("cross", "\u{f00d}");
-        m.insert("x", "\u{f00d}");
-        m.insert("info", "\u{f129}");
-        m.insert("warning", "\u{f071}");
-        m.insert("question", "\u{f128}");
-
-        // Communication
-        m.insert("mail", "\u{f0e0}");
-        m.insert("envelope", "\u{f0e0}");
-        m.insert("phone", "\u{f095}");
-
-        // Media
-        m.insert("music", "\u{f001}");
-        m.insert("camera", "\u{f030}");
-
-        // Actions
-        m.insert("search", "\u{f002}");
-        m.insert("magnifying_glass", "\u{f002}");
-        m.insert("trash", "\u{f1f8}");
-        m.insert("trash_can", "\u{f1f8}");
-
-        // Power & Connectivity
-        m.insert("battery_full", "\u{f240}");
-        m.insert("battery_half", "\u{f242}");
-        m.insert("battery_low", "\u{f243}");
-        m.insert("wifi", "\u{f1eb}");
-        m.insert("plug", "\u{f1e6}");
-
-        // Weather & Nature
-        m.insert("cloud", "\u{f0c2}");
-        m.insert("sun", "\u{f185}");
-        m.insert("moon", "\u{f186}");
-        m.insert("fire", "\u{f06d}");
-        m.insert("leaf", "\u{f06c}");
-        m.insert("paw", "\u{f1b0}");
-
-        // Development
-        m.insert("bug", "\u{f188}");
-        m.insert("insect", "\u{f188}");
-        m.insert("code", "\u{f121}");
-        m.insert("terminal", "\u{f120}");
-        m.insert("keyboard", "\u{f11c}");
-
-        // Hardware
-        m.insert("laptop", "\u{f109}");
-        m.insert("desktop", "\u{f108}");
-        m.insert("server", "\u{f233}");
-        m.insert("computer", "\u{f4b3}");
-
-        // Misc
-        m.insert("heart", "\u{f004}");
-        m.insert("star", "\u{f005}");
-        m.insert("rocket", "\u{f135}");
-        m.insert("shield", "\u{f3ed}");
-        m.insert("lightning", "\u{f0e7}");
-        m.insert("zap", "\u{f0e7}");
-
-        // Terminal variants (all map to same glyph)
-        m.insert("terminal_power", "\u{f489}");
-        m.insert("terminal_fire", "\u{f489}");
-        m.insert("terminal_bolt", "\u{f489}");
-        m.insert("terminal_flame", "\u{f489}");
-
-        m
+/// Which variant of each built-in symbol to render
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SymbolSet {
+    /// Nerd Font / powerline glyphs (requires a patched font)
+    #[default]
+    Nerd,
+    /// Standard Unicode characters available in most fonts
+    Unicode,
+    /// Plain ASCII for web consoles and minimal terminals
+    Ascii,
+}
+
+impl SymbolSet {
+    /// Parse a set name as used by `symbol_set` and `ZUSH_SYMBOLS`
+    pub fn parse(name: &str) -> Result<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "nerd" | "nerdfont" | "nerd-font" => Ok(SymbolSet::Nerd),
+            "unicode" => Ok(SymbolSet::Unicode),
+            "ascii" => Ok(SymbolSet::Ascii),
+            other => bail!("Unknown symbol set '{}'. Use: nerd, unicode, ascii", other),
+        }
+    }
+
+    /// Name used for per-set override tables like `[symbols.ascii]`
+    pub fn name(&self) -> &'static str {
+        match self {
+            SymbolSet::Nerd => "nerd",
+            SymbolSet::Unicode => "unicode",
+            SymbolSet::Ascii => "ascii",
+        }
+    }
+}
+
+/// The set used by [`resolve_builtin`], chosen once at startup
+static ACTIVE_SET: AtomicU8 = AtomicU8::new(0);
+
+/// Choose the symbol set for all later symbol resolution
+pub fn set_active_set(set: SymbolSet) {
+    ACTIVE_SET.store(set as u8, Ordering::Relaxed);
+}
+
+/// Choose the active set by name, letting `ZUSH_SYMBOLS` override
+/// `configured` (the config or theme `symbol_set`)
+pub fn select_active_set(configured: Option<&str>) -> Result<()> {
+    if let Some(set) = requested_set(configured)? {
+        set_active_set(set);
+    }
+    Ok(())
+}
+
+/// The set named by `ZUSH_SYMBOLS` or else `configured`, None when neither is set
+pub fn requested_set(configured: Option<&str>) -> Result<Option<SymbolSet>> {
+    let env = std::env::var("ZUSH_SYMBOLS").ok().filter(|s| !s.is_empty());
+    env.as_deref()
+        .or(configured)
+        .map(SymbolSet::parse)
+        .transpose()
+}
+
+/// The symbol set currently in use (Nerd Font unless changed)
+pub fn active_set() -> SymbolSet {
+    match ACTIVE_SET.load(Ordering::Relaxed) {
+        1 => SymbolSet::Unicode,
+        2 => SymbolSet::Ascii,
+        _ => SymbolSet::Nerd,
+    }
+}
+
+/// A built-in symbol with its names and per-set variants
+#[derive(Debug)]
+pub struct BuiltinSymbol {
+    /// Canonical name first, followed by aliases
+    pub names: &'static [&'static str],
+    pub nerd: &'static str,
+    pub unicode: &'static str,
+    pub ascii: &'static str,
+}
+
+impl BuiltinSymbol {
+    /// The variant for a symbol set
+    pub fn get(&self, set: SymbolSet) -> &'static str {
+        match set {
+            SymbolSet::Nerd => self.nerd,
+            SymbolSet::Unicode => self.unicode,
+            SymbolSet::Ascii => self.ascii,
+        }
+    }
+}
+
+const fn sym(
+    names: &'static [&'static str],
+    nerd: &'static str,
+    unicode: &'static str,
+    ascii: &'static str,
+) -> BuiltinSymbol {
+    BuiltinSymbol {
+        names,
+        nerd,
+        unicode,
+        ascii,
+    }
+}
+
+/// Every built-in symbol as (names, nerd, unicode, ascii)
+const SYMBOLS: &[BuiltinSymbol] = &[
+    // Powerline triangles (solid arrows)
+    sym(
+        &["triangle_right", "tri_right", "arrow_right"],
+        "\u{e0b0}",
+        "▶",
+        ">",
+    ),
+    sym(
+        &["triangle_left", "tri_left", "arrow_left"],
+        "\u{e0b2}",
+        "◀",
+        "<",
+    ),
+    // Inverted triangles
+    sym(
+        &["inverted_triangle_left", "inv_tri_right", "inv_arrow_right"],
+        "\u{e0d7}",
+        "◣",
+        "\\",
+    ),
+    sym(
+        &["inverted_triangle_right", "inv_tri_left", "inv_arrow_left"],
+        "\u{e0d6}",
+        "◢",
+        "/",
+    ),
+    // Powerline pills/rounded
+    sym(&["pill_left", "round_left"], "\u{e0b6}", "◖", "("),
+    sym(&["pill_right", "round_right"], "\u{e0b4}", "◗", ")"),
+    // Flame
+    sym(&["flame_left"], "\u{e0c0}", "▶", ">"),
+    sym(&["flame_right"], "\u{e0c2}", "◀", "<"),
+    // Trapezoid shapes
+    sym(&["trapezoid_right"], "\u{e0d2}", "▶", ">"),
+    sym(&["trapezoid_left"], "\u{e0d4}", "◀", "<"),
+    // Powerline angles (thin arrows)
+    sym(&["angle_right", "thin_right"], "\u{e0b1}", "❯", ">"),
+    sym(&["angle_left", "thin_left"], "\u{e0b3}", "❮", "<"),
+    // Powerline thin pills/rounded
+    sym(
+        &["pill_right_thin", "round_right_thin"],
+        "\u{e0b5}",
+        "❩",
+        ")",
+    ),
+    sym(&["pill_left_thin", "round_left_thin"], "\u{e0b7}", "❨", "("),
+    // Powerline circles (semi-circles)
+    sym(&["circle_right", "semicircle_right"], "\u{e0b8}", "◣", "\\"),
+    sym(&["circle_left", "semicircle_left"], "\u{e0ba}", "◢", "/"),
+    // Powerline slants/diagonal
+    sym(&["slant_right", "diagonal_right"], "\u{e0bc}", "◤", "/"),
+    sym(&["slant_left", "diagonal_left"], "\u{e0be}", "◥", "\\"),
+    // Misc shapes
+    sym(&["ice_cream"], "\u{f0efd}", "●", "o"),
+    sym(&["ice_cream_thick"], "\u{ef888}", "●", "o"),
+    sym(&["ice_cream_outline"], "\u{f082a}", "○", "o"),
+    // Slash
+    sym(&["backslash"], "\u{e216}", "\\", "\\"),
+    // Additional powerline shapes
+    sym(&["lower_triangle_right"], "\u{e0b8}", "◣", "\\"),
+    sym(&["lower_triangle_left"], "\u{e0ba}", "◢", "/"),
+    sym(&["upper_triangle_right"], "\u{e0bc}", "◤", "/"),
+    sym(&["upper_triangle_left"], "\u{e0be}", "◥", "\\"),
+    // Common nerd font icons - Git
+    sym(&["git_branch", "branch"], "\u{e0a0}", "⎇", "git:"),
+    sym(&["lock"], "\u{e0a2}", "⊘", "ro"),
+    // Common nerd font icons - UI
+    sym(&["cog", "gear"], "\u{e615}", "⚙", "*"),
+    sym(&["home"], "\u{f015}", "⌂", "~"),
+    sym(&["folder"], "\u{f07c}", "▸", "/"),
+    sym(&["folder_open"], "\u{f07b}", "▾", "/"),
+    // Time & Status
+    sym(&["timer"], "\u{f0109}", "⧗", "t"),
+    sym(&["clock"], "\u{f017}", "◷", "@"),
+    sym(&["calendar"], "\u{f133}", "▦", "#"),
+    sym(&["check"], "\u{f00c}", "✓", "ok"),
+    sym(&["cross", "x"], "\u{f00d}", "✗", "x"),
+    sym(&["info"], "\u{f129}", "ℹ", "i"),
+    sym(&["warning"], "\u{f071}", "⚠", "!"),
+    sym(&["question"], "\u{f128}", "?", "?"),
+    // Communication
+    sym(&["mail", "envelope"], "\u{f0e0}", "✉", "@"),
+    sym(&["phone"], "\u{f095}", "☎", "tel"),
+    // Media
+    sym(&["music"], "\u{f001}", "♪", "~"),
+    sym(&["camera"], "\u{f030}", "◉", "o"),
+    // Actions
+    sym(&["search", "magnifying_glass"], "\u{f002}", "⌕", "?"),
+    sym(&["trash", "trash_can"], "\u{f1f8}", "⌫", "del"),
+    // Power & Connectivity
+    sym(&["battery_full"], "\u{f240}", "■", "[#]"),
+    sym(&["battery_half"], "\u{f242}", "◧", "[=]"),
+    sym(&["battery_low"], "\u{f243}", "□", "[-]"),
+    sym(&["wifi"], "\u{f1eb}", "≋", "wifi"),
+    sym(&["plug"], "\u{f1e6}", "↯", "ac"),
+    // Weather & Nature
+    sym(&["cloud"], "\u{f0c2}", "☁", "~"),
+    sym(&["sun"], "\u{f185}", "☀", "*"),
+    sym(&["moon"], "\u{f186}", "☾", ")"),
+    sym(&["fire"], "\u{f06d}", "♨", "^"),
+    sym(&["leaf"], "\u{f06c}", "♣", "%"),
+    sym(&["paw"], "\u{f1b0}", "⁂", "*"),
+    // Development
+    sym(&["bug", "insect"], "\u{f188}", "✱", "bug"),
+    sym(&["code"], "\u{f121}", "⟨⟩", "<>"),
+    sym(&["terminal"], "\u{f120}", "❯", ">_"),
+    sym(&["keyboard"], "\u{f11c}", "⌨", "kbd"),
+    // Hardware
+    sym(&["laptop"], "\u{f109}", "▭", "pc"),
+    sym(&["desktop"], "\u{f108}", "▭", "pc"),
+    sym(&["server"], "\u{f233}", "▤", "srv"),
+    sym(&["computer"], "\u{f4b3}", "▭", "pc"),
+    // Prompt status
+    sym(&["prompt_arrow"], "❯", "❯", ">"),
+    sym(&["git_dirty"], "✗", "✗", "*"),
+    sym(&["git_clean"], "✓", "✓", "="),
+    sym(&["success"], "✓", "✓", "ok"),
+    sym(&["error"], "✖", "✖", "x"),
+    sym(&["ssh"], "\u{f08c0}", "⇄", "ssh"),
+    sym(&["root"], "\u{f084}", "#", "#"),
+    // Languages & tools
+    sym(&["python"], "🐍", "🐍", "py"),
+    sym(&["node"], "\u{e718}", "⬢", "node"),
+    sym(&["rust"], "🦀", "🦀", "rs"),
+    sym(&["docker"], "🐳", "🐳", "docker"),
+    sym(&["k8s", "kubernetes"], "☸", "☸", "k8s"),
+    sym(&["aws"], "☁", "☁", "aws"),
+    // Misc
+    sym(&["heart"], "\u{f004}", "♥", "<3"),
+    sym(&["star"], "\u{f005}", "★", "*"),
+    sym(&["rocket"], "\u{f135}", "↑", "^"),
+    sym(&["shield"], "\u{f3ed}", "⛨", "#"),
+    sym(&["lightning", "zap"], "\u{f0e7}", "↯", "!"),
+    // Terminal variants (all map to same glyph)
+    sym(
+        &[
+            "terminal_power",
+            "terminal_fire",
+            "terminal_bolt",
+            "terminal_flame",
+        ],
+        "\u{f489}",
+        "❯",
+        ">",
+    ),
+];
+
+/// Get the global built-in symbols registry, keyed by every name and alias
+pub fn builtin_symbols() -> &'static HashMap<&'static str, &'static BuiltinSymbol> {
+    static REGISTRY: OnceLock<HashMap<&'static str, &'static BuiltinSymbol>> = OnceLock::new();
+    REGISTRY.get_or_init(|| {
+        SYMBOLS
+            .iter()
+            .flat_map(|symbol| symbol.names.iter().map(move |name| (*name, symbol)))
+            .collect()
     })
 }
 
-/// Resolve a symbol name to its Unicode character
+/// Resolve a symbol name to its character in the active symbol set
 /// Returns None if the symbol is not found
 pub fn resolve_builtin(name: &str) -> Option<&'static str> {
-    builtin_symbols().get(name.trim()).copied()
+    resolve_builtin_in(name, active_set())
+}
+
+/// Resolve a symbol name to its character in a specific symbol set
+pub fn resolve_builtin_in(name: &str, set: SymbolSet) -> Option<&'static str> {
+    builtin_symbols()
+        .get(name.trim())
+        .map(|symbol| symbol.get(set))
 }
 
 /// Get all available symbol names (for documentation/help)
@@ -211,6 +328,39 @@ mod tests {
         assert!(symbols.len() > 50); // We have ~100 symbols
     }
 
+    #[test]
+    fn test_symbol_sets() {
+        assert_eq!(
+            resolve_builtin_in("triangle_right", SymbolSet::Nerd),
+            Some("\u{e0b0}")
+        );
+        assert_eq!(
+            resolve_builtin_in("tri_right", SymbolSet::Unicode),
+            Some("▶")
+        );
+        assert_eq!(
+            resolve_builtin_in("git_branch", SymbolSet::Ascii),
+            Some("git:")
+        );
+        assert_eq!(resolve_builtin_in("nope", SymbolSet::Ascii), None);
+    }
+
+    #[test]
+    fn test_every_symbol_has_fallbacks() {
+        for symbol in SYMBOLS {
+            assert!(!symbol.unicode.is_empty(), "{:?}", symbol.names);
+            assert!(symbol.ascii.is_ascii(), "{:?}", symbol.names);
+            assert!(!symbol.ascii.is_empty(), "{:?}", symbol.names);
+        }
+    }
+
+    #[test]
+    fn test_parse_symbol_set() {
+        assert_eq!(SymbolSet::parse("ASCII").unwrap(), SymbolSet::Ascii);
+        assert_eq!(SymbolSet::parse("nerd-font").unwrap(), SymbolSet::Nerd);
+        assert!(SymbolSet::parse("emoji").is_err());
+    }
+
     #[test]
     fn test_aliases_resolve_same() {
         // Verify aliases point to the same symbol
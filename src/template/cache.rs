@@ -0,0 +1,167 @@
+//! Compiled theme cache
+//!
+//! Parsing the theme TOML and preprocessing every template is the bulk of the
+//! work before a prompt can render. The result is written to the zush cache
+//! dir and reused while the theme file, config file, symbol set and binary
+//! version are unchanged.
+
+use super::SegmentPipeline;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Environment variables that change how a theme compiles
+const CACHE_ENV: &[&str] = &["ZUSH_SYMBOLS"];
+
+/// Everything derived from the theme and config before rendering
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CompiledTheme {
+    pub strict: bool,
+    /// Preprocessed templates, ready to register with Handlebars
+    pub templates: BTreeMap<String, String>,
+    pub pipeline: Option<SegmentPipeline>,
+    /// Context values for `colors.*`, `symbols.*` and `palette.*`
+    pub colors: HashMap<String, Value>,
+    pub symbols: HashMap<String, Value>,
+    pub palette: HashMap<String, String>,
+    /// Environment variable patterns templates may read (from `[env] allow`)
+    pub env_allow: Vec<String>,
+    /// `[directory.substitutions]` as (prefix or `re:` pattern, label)
+    pub path_substitutions: Vec<(String, String)>,
+}
+
+/// Identifies the inputs a compiled theme was built from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ThemeCacheKey {
+    version: String,
+    /// Theme and config files with their modification times
+    files: Vec<(PathBuf, Option<u128>)>,
+    env: Vec<(String, Option<String>)>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheFile {
+    key: ThemeCacheKey,
+    theme: CompiledTheme,
+}
+
+impl ThemeCacheKey {
+    /// Key for the given theme/config files (missing files are skipped)
+    pub fn new(files: &[Option<&Path>]) -> Self {
+        let files = files
+            .iter()
+            .flatten()
+            .map(|path| (path.to_path_buf(), modified_nanos(path)))
+            .collect();
+        let env = CACHE_ENV
+            .iter()
+            .map(|name| (name.to_string(), std::env::var(name).ok()))
+            .collect();
+
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            files,
+            env,
+        }
+    }
+
+    /// One cache file per combination of theme and config paths, so edits
+    /// overwrite the previous entry instead of accumulating
+    fn cache_path(&self) -> PathBuf {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::{Hash, Hasher};
+
+        let mut hasher = DefaultHasher::new();
+        for (path, _) in &self.files {
+            path.hash(&mut hasher);
+        }
+        let hash = hasher.finish();
+
+        crate::git::cache_dir().join(format!("theme-{:x}.json", hash))
+    }
+}
+
+fn modified_nanos(path: &Path) -> Option<u128> {
+    let modified = fs::metadata(path).ok()?.modified().ok()?;
+    Some(modified.duration_since(UNIX_EPOCH).ok()?.as_nanos())
+}
+
+/// Load a compiled theme if one was stored for exactly this key
+pub fn load(key: &ThemeCacheKey) -> Option<CompiledTheme> {
+    let contents = fs::read_to_string(key.cache_path()).ok()?;
+    let file: CacheFile = serde_json::from_str(&contents).ok()?;
+    (file.key == *key).then_some(file.theme)
+}
+
+/// Store a compiled theme, replacing any older entry for the same files
+pub fn store(key: &ThemeCacheKey, theme: &CompiledTheme) -> Result<()> {
+    let path = key.cache_path();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let file = CacheFile {
+        key: key.clone(),
+        theme: theme.clone(),
+    };
+    let json = serde_json::to_string(&file)?;
+
+    // Write then rename so a concurrent prompt never reads a partial file
+    let tmp = path.with_extension(format!("tmp.{}", std::process::id()));
+    fs::write(&tmp, json).with_context(|| format!("Failed to write theme cache: {:?}", tmp))?;
+    fs::rename(&tmp, &path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_tracks_file_changes() {
+        let dir = std::env::temp_dir().join(format!("zush-theme-cache-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let theme = dir.join("theme.toml");
+        fs::write(&theme, "[templates]\nmain = \"a\"").unwrap();
+
+        let key = ThemeCacheKey::new(&[Some(theme.as_path()), None]);
+        assert_eq!(key, ThemeCacheKey::new(&[Some(theme.as_path())]));
+        assert_eq!(key.files.len(), 1);
+
+        // A newer mtime gives a different key for the same cache file
+        let file = fs::File::options().write(true).open(&theme).unwrap();
+        file.set_modified(UNIX_EPOCH + std::time::Duration::from_secs(1))
+            .unwrap();
+        let changed = ThemeCacheKey::new(&[Some(theme.as_path())]);
+        assert_ne!(key, changed);
+        assert_eq!(key.cache_path(), changed.cache_path());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_cache_file_roundtrip() {
+        let key = ThemeCacheKey::new(&[]);
+        let mut theme = CompiledTheme {
+            strict: true,
+            ..Default::default()
+        };
+        theme
+            .templates
+            .insert("main".to_string(), "{{user}}".to_string());
+
+        let json = serde_json::to_string(&CacheFile {
+            key: key.clone(),
+            theme,
+        })
+        .unwrap();
+        let file: CacheFile = serde_json::from_str(&json).unwrap();
+        assert_eq!(file.key, key);
+        assert!(file.theme.strict);
+        assert_eq!(file.theme.templates["main"], "{{user}}");
+    }
+}
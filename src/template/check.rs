@@ -0,0 +1,680 @@
+//! Static checks for theme templates (`zush-prompt theme check`)
+//!
+//! Templates are checked against their original source so every problem can
+//! be reported with the line and column the author sees in the theme file.
+//! The style-tag syntax is scanned directly, then the text is compiled by
+//! Handlebars to find syntax errors and references to unknown variables.
+
+use super::context::is_context_variable;
+use super::preprocessor::{StyleTag, TemplatePreprocessor};
+use super::{SegmentDef, TemplateEngine};
+use crate::color::{ColorScheme, Palette};
+use anyhow::Result;
+use handlebars::template::{HelperTemplate, Parameter, Template, TemplateElement};
+use handlebars::Path;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// How serious a diagnostic is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    /// Likely a mistake, but can be legitimate (e.g. tags balanced across branches)
+    Warning,
+}
+
+/// A problem found in a template, positioned in the original template text
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub template: String,
+    pub line: usize,
+    pub column: usize,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(
+            f,
+            "{}:{}:{}: {}: {}",
+            self.template, self.line, self.column, severity, self.message
+        )
+    }
+}
+
+/// An opened style tag waiting for its closing tag
+struct OpenTag {
+    name: String,
+    line: usize,
+    column: usize,
+    /// Handlebars block depth at the tag, tags inside conditionals may
+    /// legitimately be closed in another branch
+    depth: usize,
+}
+
+/// Checks templates against a theme's colors, symbols and segments
+pub struct ThemeChecker {
+    engine: TemplateEngine,
+    preprocessor: TemplatePreprocessor,
+    symbols: HashMap<String, String>,
+    segments: HashMap<String, SegmentDef>,
+    color_names: HashSet<String>,
+    symbol_names: HashSet<String>,
+    /// Names defined under `[vars]`, available as context variables
+    var_names: HashSet<String>,
+}
+
+impl ThemeChecker {
+    /// `color_names`/`symbol_names` are the keys available as `colors.*` and `symbols.*`
+    pub fn new(
+        colors: HashMap<String, String>,
+        symbols: HashMap<String, String>,
+        segments: HashMap<String, SegmentDef>,
+        color_names: HashSet<String>,
+        symbol_names: HashSet<String>,
+    ) -> Result<Self> {
+        let mut engine = TemplateEngine::new()?;
+        engine.set_colors(colors.clone());
+        engine.set_symbols(symbols.clone());
+        engine.add_segments(segments.clone());
+
+        Ok(Self {
+            engine,
+            preprocessor: TemplatePreprocessor::with_symbols(colors, symbols.clone()),
+            symbols,
+            segments,
+            color_names,
+            symbol_names,
+            var_names: HashSet::new(),
+        })
+    }
+
+    /// Treat the theme's `[vars]` names as defined context variables
+    pub fn set_var_names(&mut self, names: impl IntoIterator<Item = String>) {
+        self.var_names = names.into_iter().collect();
+    }
+
+    /// Check a single template, returning diagnostics in source order
+    pub fn check_template(&mut self, name: &str, source: &str) -> Vec<Diagnostic> {
+        let mut check = TemplateCheck {
+            checker: self,
+            template: name,
+            diagnostics: Vec::new(),
+        };
+
+        let handlebars_source = check.scan_markup(source);
+        match Template::compile(&handlebars_source) {
+            Ok(template) => check.walk(&template, false),
+            Err(e) => {
+                let (line, column) = e.pos().unwrap_or((1, 1));
+                check.push(line, column, Severity::Error, e.reason().to_string());
+            }
+        }
+
+        let mut diagnostics = check.diagnostics;
+        diagnostics.sort_by_key(|d| (d.line, d.column));
+
+        // Anything the scan missed still fails when the template is registered
+        if !diagnostics.iter().any(|d| d.severity == Severity::Error) {
+            if let Err(e) = self.engine.register_template(name, source) {
+                diagnostics.push(Diagnostic {
+                    template: name.to_string(),
+                    line: 1,
+                    column: 1,
+                    severity: Severity::Error,
+                    message: format!("{:#}", e),
+                });
+            }
+        }
+
+        diagnostics
+    }
+}
+
+/// State for checking one template
+struct TemplateCheck<'a> {
+    checker: &'a ThemeChecker,
+    template: &'a str,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl TemplateCheck<'_> {
+    fn push(&mut self, line: usize, column: usize, severity: Severity, message: String) {
+        self.diagnostics.push(Diagnostic {
+            template: self.template.to_string(),
+            line,
+            column,
+            severity,
+            message,
+        });
+    }
+
+    /// Check style tags, @symbols and segment syntax, returning the template
+    /// with segment syntax blanked out (keeping positions) for Handlebars
+    fn scan_markup(&mut self, source: &str) -> String {
+        let chars: Vec<char> = source.chars().collect();
+        let mut output: Vec<char> = Vec::with_capacity(chars.len());
+        let mut open_tags: Vec<OpenTag> = Vec::new();
+        let mut segment_refs: Vec<(String, usize, usize)> = Vec::new();
+        let mut defined_segments: HashSet<String> = HashSet::new();
+        let mut depth = 0usize;
+        let (mut line, mut column) = (1, 1);
+        let mut i = 0;
+
+        while i < chars.len() {
+            let start = i;
+
+            if chars[i] == '{' && chars.get(i + 1) == Some(&'{') {
+                let close = (i + 2..chars.len().saturating_sub(1))
+                    .find(|&j| chars[j] == '}' && chars[j + 1] == '}');
+                let Some(close) = close else {
+                    // Let Handlebars report the unterminated expression
+                    output.extend_from_slice(&chars[i..]);
+                    break;
+                };
+                let inner: String = chars[i + 2..close].iter().collect();
+                let inner = inner.trim();
+                i = close + 2;
+
+                let blank = if let Some(params) = inner.strip_prefix("segment") {
+                    if params.trim_start().starts_with('"') {
+                        if let Some(name) = self.check_segment_def(params, line, column) {
+                            defined_segments.insert(name);
+                        }
+                        true
+                    } else {
+                        false
+                    }
+                } else if inner == "endsegment" {
+                    true
+                } else if let Some(name) = inner.strip_prefix("seg:") {
+                    segment_refs.push((name.trim().to_string(), line, column));
+                    true
+                } else {
+                    if inner.starts_with('#') {
+                        depth += 1;
+                    } else if inner.starts_with('/') {
+                        depth = depth.saturating_sub(1);
+                    }
+                    false
+                };
+
+                for &c in &chars[start..i] {
+                    output.push(if blank && c != '\n' { ' ' } else { c });
+                }
+            } else if chars[i] == '(' {
+                match TemplatePreprocessor::parse_style_tag(&chars, i) {
+                    Ok(Some((tag, end))) => {
+                        self.check_style_tag(tag, line, column, depth, &mut open_tags);
+                        i = end;
+                    }
+                    _ => i += 1,
+                }
+                output.extend_from_slice(&chars[start..i]);
+            } else if chars[i] == '@' {
+                i += 1;
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                let name: String = chars[start + 1..i].iter().collect();
+                if !name.is_empty() && !self.is_symbol(&name) {
+                    self.push(
+                        line,
+                        column,
+                        Severity::Error,
+                        format!("unknown symbol '@{}'", name),
+                    );
+                }
+                output.extend_from_slice(&chars[start..i]);
+            } else {
+                output.push(chars[i]);
+                i += 1;
+            }
+
+            for &c in &chars[start..i] {
+                if c == '\n' {
+                    line += 1;
+                    column = 1;
+                } else {
+                    column += 1;
+                }
+            }
+        }
+
+        for tag in open_tags {
+            let severity = if tag.depth > 0 {
+                Severity::Warning
+            } else {
+                Severity::Error
+            };
+            self.push(
+                tag.line,
+                tag.column,
+                severity,
+                format!("unclosed style tag ({})", tag.name),
+            );
+        }
+
+        for (name, line, column) in segment_refs {
+            if !self.checker.segments.contains_key(&name) && !defined_segments.contains(&name) {
+                self.push(
+                    line,
+                    column,
+                    Severity::Error,
+                    format!("unknown segment '{}'", name),
+                );
+            }
+        }
+
+        output.into_iter().collect()
+    }
+
+    /// Check one style tag and track it for balance checking
+    fn check_style_tag(
+        &mut self,
+        tag: StyleTag,
+        line: usize,
+        column: usize,
+        depth: usize,
+        open_tags: &mut Vec<OpenTag>,
+    ) {
+        let name = canonical_style(&tag.name).to_string();
+
+        if tag.is_closing {
+            if let Some(idx) = open_tags.iter().rposition(|t| t.name == name) {
+                open_tags.remove(idx);
+            } else {
+                let severity = if depth > 0 {
+                    Severity::Warning
+                } else {
+                    Severity::Error
+                };
+                self.push(
+                    line,
+                    column,
+                    severity,
+                    format!("closing tag (/{}) has no matching opening tag", tag.name),
+                );
+            }
+            return;
+        }
+
+        match (name.as_str(), tag.args.as_deref()) {
+            ("fg" | "bg", None) => self.push(
+                line,
+                column,
+                Severity::Error,
+                format!("({}) requires a color", name),
+            ),
+            ("fg" | "bg", Some(args)) => {
+                let result = match args.strip_prefix("scale:") {
+                    Some(scale) => self
+                        .checker
+                        .preprocessor
+                        .scale_expression(&name, scale)
+                        .map(|_| ()),
+                    None => self.checker.preprocessor.resolve_color(args).map(|_| ()),
+                };
+                if let Err(e) = result {
+                    self.push(line, column, Severity::Error, e.to_string());
+                }
+            }
+            ("gradient", args) => {
+                let result = self
+                    .checker
+                    .preprocessor
+                    .gradient_expression(args.unwrap_or(""));
+                if let Err(e) = result {
+                    self.push(line, column, Severity::Error, e.to_string());
+                }
+            }
+            ("sym", args) => {
+                let symbol = args.unwrap_or("");
+                if !self.is_symbol(symbol) {
+                    self.push(
+                        line,
+                        column,
+                        Severity::Error,
+                        format!("unknown symbol '{}'", symbol),
+                    );
+                }
+            }
+            _ => {}
+        }
+
+        if name != "sym" {
+            open_tags.push(OpenTag {
+                name,
+                line,
+                column,
+                depth,
+            });
+        }
+    }
+
+    /// Check a `{{segment "name" ...}}` definition, returning its name
+    fn check_segment_def(&mut self, params: &str, line: usize, column: usize) -> Option<String> {
+        let preprocessor = &self.checker.preprocessor;
+        let segment = match preprocessor.parse_segment_params(params) {
+            Ok(segment) => segment,
+            Err(e) => {
+                self.push(line, column, Severity::Error, e.to_string());
+                return None;
+            }
+        };
+
+        let mut errors = Vec::new();
+        for color in [&segment.bg, &segment.fg].into_iter().flatten() {
+            if let Err(e) = preprocessor.resolve_color(color) {
+                errors.push(e.to_string());
+            }
+        }
+        for shape in [&segment.sep, &segment.left_cap].into_iter().flatten() {
+            if let Err(e) = preprocessor.get_separator_symbol(shape) {
+                errors.push(e.to_string());
+            }
+        }
+        for message in errors {
+            self.push(line, column, Severity::Error, message);
+        }
+
+        Some(segment.name)
+    }
+
+    fn is_symbol(&self, name: &str) -> bool {
+        let name = name.trim();
+        self.checker.symbols.contains_key(name) || crate::symbols::resolve_builtin(name).is_some()
+    }
+
+    /// Walk compiled template elements looking for unknown variables
+    /// `scoped` is set inside blocks that change the context (each, with)
+    fn walk(&mut self, template: &Template, scoped: bool) {
+        for (element, mapping) in template.elements.iter().zip(&template.mapping) {
+            let (line, column) = (mapping.0, mapping.1);
+            match element {
+                TemplateElement::Expression(helper) | TemplateElement::HtmlExpression(helper) => {
+                    self.check_expression(helper, line, column, scoped);
+                }
+                TemplateElement::HelperBlock(helper) => {
+                    self.check_expression(helper, line, column, scoped);
+                    let changes_scope = match &helper.name {
+                        Parameter::Name(name) | Parameter::Path(Path::Relative((_, name))) => {
+                            !self.checker.engine.is_helper(name) || name == "each" || name == "with"
+                        }
+                        _ => true,
+                    };
+                    if let Some(inner) = &helper.template {
+                        self.walk(inner, scoped || changes_scope);
+                    }
+                    if let Some(inverse) = &helper.inverse {
+                        self.walk(inverse, scoped);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn check_expression(
+        &mut self,
+        helper: &HelperTemplate,
+        line: usize,
+        column: usize,
+        scoped: bool,
+    ) {
+        let has_args = !helper.params.is_empty() || !helper.hash.is_empty();
+        let name = match &helper.name {
+            Parameter::Name(name) => Some(name.as_str()),
+            Parameter::Path(Path::Relative((_, raw))) => Some(raw.as_str()),
+            _ => None,
+        };
+        match name {
+            Some(name) if self.checker.engine.is_helper(name) => {}
+            Some(name) if has_args => self.push(
+                line,
+                column,
+                Severity::Error,
+                format!("unknown helper '{}'", name),
+            ),
+            Some(name) => self.check_variable(name, line, column, scoped),
+            None => self.check_parameter(&helper.name, line, column, scoped),
+        }
+
+        if name == Some("format_time") {
+            self.check_time_param(helper.params.first(), line, column);
+        }
+        for param in helper.params.iter().chain(helper.hash.values()) {
+            self.check_parameter(param, line, column, scoped);
+        }
+    }
+
+    /// A literal first `format_time` parameter that is neither a zone nor a
+    /// timestamp silently renders the current time
+    fn check_time_param(&mut self, param: Option<&Parameter>, line: usize, column: usize) {
+        use crate::format::{is_time_zone, parse_timestamp};
+
+        if let Some(Parameter::Literal(serde_json::Value::String(s))) = param {
+            if !is_time_zone(s) && parse_timestamp(s).is_none() {
+                self.push(
+                    line,
+                    column,
+                    Severity::Error,
+                    format!(
+                        "format_time: \"{}\" is neither a time zone nor a timestamp",
+                        s
+                    ),
+                );
+            }
+        }
+    }
+
+    fn check_parameter(&mut self, param: &Parameter, line: usize, column: usize, scoped: bool) {
+        match param {
+            Parameter::Name(name) => self.check_variable(name, line, column, scoped),
+            Parameter::Path(Path::Relative((_, raw))) => {
+                self.check_variable(raw, line, column, scoped)
+            }
+            Parameter::Subexpression(subexpr) => {
+                let name = subexpr.name();
+                let has_args = subexpr.params().is_some_and(|p| !p.is_empty())
+                    || subexpr.hash().is_some_and(|h| !h.is_empty());
+                if self.checker.engine.is_helper(name) {
+                    // Helper call, arguments are checked below
+                } else if has_args {
+                    self.push(
+                        line,
+                        column,
+                        Severity::Error,
+                        format!("unknown helper '{}'", name),
+                    );
+                } else {
+                    self.check_variable(name, line, column, scoped);
+                }
+                for param in subexpr.params().into_iter().flatten() {
+                    self.check_parameter(param, line, column, scoped);
+                }
+                for param in subexpr.hash().into_iter().flat_map(|h| h.values()) {
+                    self.check_parameter(param, line, column, scoped);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn check_variable(&mut self, raw: &str, line: usize, column: usize, scoped: bool) {
+        if scoped || raw.starts_with('@') || raw.starts_with("this") || raw.starts_with('.') {
+            return;
+        }
+
+        let mut parts = raw.split(['.', '/']);
+        let root = parts.next().unwrap_or_default();
+        let key = parts.next();
+
+        let message = match (root, key) {
+            ("colors", Some(key)) if !self.checker.color_names.contains(key) => {
+                format!("unknown color 'colors.{}'", key)
+            }
+            ("symbols", Some(key)) if !self.checker.symbol_names.contains(key) => {
+                format!("unknown symbol 'symbols.{}'", key)
+            }
+            ("palette", Some(key)) if !is_palette_role(key) => {
+                format!("unknown palette color 'palette.{}'", key)
+            }
+            (root, _) if !is_context_variable(root) && !self.checker.var_names.contains(root) => {
+                format!("undefined variable '{}'", raw)
+            }
+            _ => return,
+        };
+        self.push(line, column, Severity::Error, message);
+    }
+}
+
+/// Every palette has the same roles, so any one of them lists the valid keys
+fn is_palette_role(key: &str) -> bool {
+    Palette::select(None)
+        .map(|palette| palette.entries().iter().any(|(role, _)| *role == key))
+        .unwrap_or(false)
+}
+
+/// Map style tag aliases to one name so (b)...(/bold) balances
+fn canonical_style(name: &str) -> &str {
+    match name {
+        "b" => "bold",
+        "d" => "dim",
+        "i" | "em" => "italic",
+        "u" => "underline",
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checker() -> ThemeChecker {
+        let colors = HashMap::from([("red".to_string(), "#ff0000".to_string())]);
+        let symbols = HashMap::from([("arrow".to_string(), "❯".to_string())]);
+        let color_names = colors.keys().cloned().collect();
+        let symbol_names = symbols.keys().cloned().collect();
+        ThemeChecker::new(colors, symbols, HashMap::new(), color_names, symbol_names).unwrap()
+    }
+
+    fn messages(source: &str) -> Vec<String> {
+        checker()
+            .check_template("main", source)
+            .iter()
+            .map(|d| d.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_clean_template() {
+        let source = "(bold)(fg red){{user}}(/fg)(/bold){{reset}} @arrow {{#if git_branch}}{{colors.red}}{{/if}}\n{{#each modules}}{{content}}{{/each}}";
+        assert!(messages(source).is_empty(), "{:?}", messages(source));
+    }
+
+    #[test]
+    fn test_style_tag_errors() {
+        assert_eq!(
+            messages("ok\n  (fg #ff00zz)x(/fg) (bg nope)y(/bg)"),
+            vec![
+                "main:2:3: error: Invalid hex color: #ff00zz",
+                "main:2:22: error: Unknown color name 'nope'. Define it in the [colors] section of your theme or use a hex color like #ff0000",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unbalanced_tags() {
+        assert_eq!(
+            messages("(bold)x (/fg)"),
+            vec![
+                "main:1:1: error: unclosed style tag (bold)",
+                "main:1:9: error: closing tag (/fg) has no matching opening tag",
+            ]
+        );
+        // Aliases balance each other
+        assert!(messages("(b)x(/bold)").is_empty());
+        // Tags closed in another conditional branch are only warnings
+        assert_eq!(
+            messages("{{#if user}}(bg red){{else}}(bg #000000){{/if}}x(/bg)"),
+            vec!["main:1:13: warning: unclosed style tag (bg)"]
+        );
+    }
+
+    #[test]
+    fn test_unknown_symbols() {
+        assert_eq!(
+            messages("@arrow @nope\n(sym bogus)"),
+            vec![
+                "main:1:8: error: unknown symbol '@nope'",
+                "main:2:1: error: unknown symbol 'bogus'",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_undefined_variables() {
+        assert_eq!(
+            messages(
+                "{{usr}}\n{{#if (gt git_ahed 0)}}{{colors.blue}}{{/if}} {{truncate pwd_short 20}}"
+            ),
+            vec![
+                "main:1:1: error: undefined variable 'usr'",
+                "main:2:1: error: undefined variable 'git_ahed'",
+                "main:2:24: error: unknown color 'colors.blue'",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_format_time_param() {
+        assert!(messages(r#"{{format_time "UTC" "%H"}} {{format_time time "%H"}}"#).is_empty());
+        assert_eq!(
+            messages(r#"{{format_time "soon" "%H"}}"#),
+            vec!["main:1:1: error: format_time: \"soon\" is neither a time zone nor a timestamp"]
+        );
+    }
+
+    #[test]
+    fn test_var_names() {
+        let mut checker = checker();
+        checker.set_var_names(["is_prod".to_string()]);
+        let diagnostics = checker.check_template("main", "{{#if is_prod}}!{{/if}}{{is_dev}}");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "main:1:24: error: undefined variable 'is_dev'"
+        );
+    }
+
+    #[test]
+    fn test_palette_keys() {
+        assert_eq!(
+            messages("{{fg palette.red}}{{palette.background}} {{palette.orange}}"),
+            vec!["main:1:42: error: unknown palette color 'palette.orange'"]
+        );
+    }
+
+    #[test]
+    fn test_handlebars_syntax_error() {
+        let diagnostics = checker().check_template("main", "x\n{{#if user}}open");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+        assert_eq!(diagnostics[0].line, 2);
+    }
+
+    #[test]
+    fn test_segments() {
+        let source = "{{segment \"dir\" bg=\"red\" fg=\"#ffffff\"}}{{pwd_short}}{{endsegment}}{{seg:dir}} {{seg:missing}}";
+        assert_eq!(
+            messages(source),
+            vec!["main:1:79: error: unknown segment 'missing'"]
+        );
+    }
+}
@@ -0,0 +1,132 @@
+//! Variables the prompt renderer places in the template context
+//!
+//! `render_prompt` fills in the fallbacks and `theme check` accepts exactly
+//! these names, so adding a variable here covers both.
+
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+/// Value a variable gets when the renderer found nothing for it
+/// (e.g. the git variables outside a repository)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fallback {
+    /// Always set by the renderer itself
+    Set,
+    Str,
+    Zero,
+    False,
+}
+
+/// Every built-in context variable with its fallback
+pub const CONTEXT_VARIABLES: &[(&str, Fallback)] = &[
+    ("exit_code", Fallback::Set),
+    ("execution_time", Fallback::Set),
+    ("execution_time_ms", Fallback::Set),
+    ("execution_time_s", Fallback::Set),
+    ("time", Fallback::Set),
+    ("user", Fallback::Set),
+    ("host", Fallback::Set),
+    ("pwd", Fallback::Set),
+    ("pwd_short", Fallback::Set),
+    ("is_ssh", Fallback::Set),
+    ("ssh", Fallback::Set),
+    ("jobs", Fallback::Set),
+    ("shell", Fallback::Set),
+    ("history_number", Fallback::Set),
+    ("virtual_env", Fallback::Set),
+    ("git_root", Fallback::Str),
+    ("git_repo_name", Fallback::Str),
+    ("pwd_in_repo", Fallback::Str),
+    ("git_branch", Fallback::Str),
+    ("git_state", Fallback::Str),
+    ("git_state_progress", Fallback::Str),
+    ("git_staged", Fallback::Zero),
+    ("git_modified", Fallback::Zero),
+    ("git_added", Fallback::Zero),
+    ("git_deleted", Fallback::Zero),
+    ("git_renamed", Fallback::Zero),
+    ("git_untracked", Fallback::Zero),
+    ("git_conflicted", Fallback::Zero),
+    ("git_stash", Fallback::Zero),
+    ("git_ahead", Fallback::Zero),
+    ("git_behind", Fallback::Zero),
+    ("git_upstream", Fallback::Str),
+    ("git_upstream_gone", Fallback::False),
+    ("git_remote_url", Fallback::Str),
+    ("git_remote_host", Fallback::Str),
+    ("git_detached", Fallback::False),
+    ("git_tag", Fallback::Str),
+    ("git_describe", Fallback::Str),
+    ("git_worktree", Fallback::Str),
+    ("git_submodules_dirty", Fallback::Zero),
+    ("git_lines_added", Fallback::Zero),
+    ("git_lines_deleted", Fallback::Zero),
+    ("git_commit_short", Fallback::Str),
+    ("git_commit_subject", Fallback::Str),
+    ("git_commit_author", Fallback::Str),
+    ("git_commit_ts", Fallback::Zero),
+    ("git_commit_age", Fallback::Str),
+    ("git_dirty_ts", Fallback::Zero),
+    ("git_dirty_age", Fallback::Str),
+    ("git_from_cache", Fallback::False),
+    ("git_async_pending", Fallback::False),
+    ("git_stale", Fallback::False),
+    ("vcs", Fallback::Str),
+    ("jj_root", Fallback::Str),
+    ("jj_change_id", Fallback::Str),
+    ("jj_description", Fallback::Str),
+    ("jj_bookmarks", Fallback::Str),
+    ("jj_conflict", Fallback::False),
+    ("jj_empty", Fallback::False),
+    ("modules", Fallback::Set),
+    ("colors", Fallback::Set),
+    ("symbols", Fallback::Set),
+    ("palette", Fallback::Set),
+    ("terminal_width", Fallback::Set),
+    ("first_line", Fallback::Set),
+    ("segments_left", Fallback::Set),
+    ("segments_center", Fallback::Set),
+    ("segments_right", Fallback::Set),
+];
+
+/// Whether `name` is a built-in context variable
+pub fn is_context_variable(name: &str) -> bool {
+    CONTEXT_VARIABLES.iter().any(|(var, _)| *var == name)
+}
+
+/// Give every variable that is still missing its fallback value
+pub fn insert_context_defaults(context: &mut HashMap<String, Value>) {
+    for (name, fallback) in CONTEXT_VARIABLES {
+        let value = match fallback {
+            Fallback::Set => continue,
+            Fallback::Str => json!(""),
+            Fallback::Zero => json!(0),
+            Fallback::False => json!(false),
+        };
+        context.entry(name.to_string()).or_insert(value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_context_defaults() {
+        let mut context = HashMap::new();
+        context.insert("git_branch".to_string(), json!("main"));
+        insert_context_defaults(&mut context);
+
+        assert_eq!(context["git_branch"], "main");
+        assert_eq!(context["git_stash"], 0);
+        assert_eq!(context["git_detached"], false);
+        assert_eq!(context["jj_change_id"], "");
+        // Renderer-owned variables are left alone
+        assert!(!context.contains_key("pwd"));
+
+        let mut names: Vec<_> = CONTEXT_VARIABLES.iter().map(|(name, _)| name).collect();
+        names.sort();
+        names.dedup();
+        assert_eq!(names.len(), CONTEXT_VARIABLES.len(), "duplicate variable");
+    }
+}
@@ -0,0 +1,134 @@
+//! Environment variable access for templates
+//!
+//! `{{env "NAME" "default"}}` and `{{env_set "NAME"}}` only see variables
+//! listed in the user's config (`[env] allow = ["KUBECONFIG", "TF_*"]`).
+//! The allowlist comes from config.toml, never from a theme, so a shared
+//! theme can't read tokens or other secrets from the environment.
+
+use handlebars::{Context, Handlebars, Helper, HelperDef, RenderContext, RenderError, ScopedJson};
+use serde_json::json;
+use std::sync::Arc;
+
+/// Which environment variables templates may read
+#[derive(Debug, Clone, Default)]
+pub struct EnvAllowlist {
+    patterns: Vec<String>,
+}
+
+impl EnvAllowlist {
+    /// Patterns are exact names or globs where `*` matches any run of characters
+    pub fn new(patterns: Vec<String>) -> Self {
+        Self { patterns }
+    }
+
+    pub fn is_allowed(&self, name: &str) -> bool {
+        self.patterns
+            .iter()
+            .any(|pattern| glob_match(pattern, name))
+    }
+
+    /// The variable's value if it is allowed, set and non-empty
+    pub fn get(&self, name: &str) -> Option<String> {
+        if !self.is_allowed(name) {
+            return None;
+        }
+        std::env::var(name).ok().filter(|value| !value.is_empty())
+    }
+}
+
+/// Match `name` against a pattern where `*` matches any (possibly empty) run
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let Some((prefix, rest)) = pattern.split_once('*') else {
+        return pattern == name;
+    };
+    let Some(mut remaining) = name.strip_prefix(prefix) else {
+        return false;
+    };
+
+    let mut parts = rest.split('*').peekable();
+    while let Some(part) = parts.next() {
+        if parts.peek().is_none() {
+            // The last part has to match the end of the name
+            return remaining.len() >= part.len() && remaining.ends_with(part);
+        }
+        match remaining.find(part) {
+            Some(index) => remaining = &remaining[index + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+/// `{{env "NAME" "default"}}`: value of an allowed variable, else the default
+pub(super) struct EnvHelper(pub(super) Arc<EnvAllowlist>);
+
+impl HelperDef for EnvHelper {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'rc>, RenderError> {
+        let name = h.param(0).and_then(|p| p.value().as_str()).unwrap_or("");
+        let value = self
+            .0
+            .get(name)
+            .map(|value| json!(value))
+            .unwrap_or_else(|| {
+                h.param(1)
+                    .map(|default| default.value().clone())
+                    .unwrap_or_else(|| json!(""))
+            });
+        Ok(ScopedJson::Derived(value))
+    }
+}
+
+/// `{{#if (env_set "NAME")}}`: whether an allowed variable is set and non-empty
+pub(super) struct EnvSetHelper(pub(super) Arc<EnvAllowlist>);
+
+impl HelperDef for EnvSetHelper {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'rc>, RenderError> {
+        let name = h.param(0).and_then(|p| p.value().as_str()).unwrap_or("");
+        Ok(ScopedJson::Derived(json!(self.0.get(name).is_some())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("KUBECONFIG", "KUBECONFIG"));
+        assert!(!glob_match("KUBECONFIG", "KUBECONFIG_X"));
+        assert!(glob_match("TF_*", "TF_WORKSPACE"));
+        assert!(glob_match("TF_*", "TF_"));
+        assert!(!glob_match("TF_*", "XTF_A"));
+        assert!(glob_match("*_PROFILE", "AWS_PROFILE"));
+        assert!(glob_match("A*B*C", "AxxBxxC"));
+        assert!(!glob_match("A*B*C", "AxxCxxB"));
+        assert!(glob_match("*", "ANYTHING"));
+    }
+
+    #[test]
+    fn test_allowlist_gates_values() {
+        std::env::set_var("ZUSH_TEST_ENV_ALLOWED", "dev");
+        std::env::set_var("ZUSH_TEST_ENV_EMPTY", "");
+        let allow = EnvAllowlist::new(vec![
+            "ZUSH_TEST_ENV_A*".into(),
+            "ZUSH_TEST_ENV_EMPTY".into(),
+        ]);
+
+        assert_eq!(allow.get("ZUSH_TEST_ENV_ALLOWED"), Some("dev".to_string()));
+        assert_eq!(allow.get("ZUSH_TEST_ENV_EMPTY"), None);
+        assert_eq!(allow.get("HOME"), None);
+        assert_eq!(EnvAllowlist::default().get("ZUSH_TEST_ENV_ALLOWED"), None);
+    }
+}
@@ -1,22 +1,104 @@
 #![allow(dead_code)]
 
-use crate::color::Color;
-use anyhow::{Context as AnyhowContext, Result};
-use handlebars::{Context, Handlebars, Helper, HelperResult, Output, RenderContext};
+use crate::color::{Color, ColorScale, ColorSpace, Gradient};
+use crate::config::SegmentConfig;
+use anyhow::{bail, Context as AnyhowContext, Result};
+use handlebars::{
+    Context, Handlebars, Helper, HelperDef, HelperResult, JsonRender, Output, RenderContext,
+    RenderError, RenderErrorReason, Renderable, StringOutput,
+};
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
-use std::collections::HashMap;
-
+use serde_json::{json, Value};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+pub mod cache;
+mod check;
+mod context;
+mod env;
+mod path;
+mod powerline;
 mod preprocessor;
+pub use self::cache::{CompiledTheme, ThemeCacheKey};
+pub use self::check::{Severity, ThemeChecker};
+pub use self::context::insert_context_defaults;
+use self::env::{EnvAllowlist, EnvHelper, EnvSetHelper};
+use self::path::FormatPathHelper;
+pub use self::path::PathSubstitutions;
+pub use self::powerline::SegmentPipeline;
 pub use self::preprocessor::{SegmentDef, TemplatePreprocessor};
 
+/// Typed value of a rendered `[vars]` entry
+fn var_value(rendered: &str) -> Value {
+    match rendered {
+        "true" => json!(true),
+        "false" => json!(false),
+        // Only when it prints back the same, so "007" or "1e3" stay text
+        _ => match rendered.parse::<i64>() {
+            Ok(n) if n.to_string() == rendered => json!(n),
+            _ => json!(rendered),
+        },
+    }
+}
+
 /// Template engine for prompt rendering
 pub struct TemplateEngine {
-    handlebars: Handlebars<'static>,
+    handlebars: RefCell<Handlebars<'static>>,
     context_data: HashMap<String, Value>,
     colors: HashMap<String, String>,
     symbols: HashMap<String, String>,
     segments: HashMap<String, SegmentDef>,
+    pipeline: Option<SegmentPipeline>,
+    helper_names: Vec<&'static str>,
+    /// Preprocessed source of every registered template, for the theme cache
+    processed: BTreeMap<String, String>,
+    /// Templates from a compiled theme, compiled by Handlebars on first render
+    uncompiled: RefCell<HashMap<String, String>>,
+    path_substitutions: Arc<PathSubstitutions>,
+}
+
+/// Template name prefix for `[vars]` entries
+const VAR_PREFIX: &str = "var:";
+
+/// Helpers built into Handlebars itself
+const BUILTIN_HELPERS: &[&str] = &[
+    "if", "unless", "each", "with", "lookup", "raw", "log", "eq", "ne", "gt", "gte", "lt", "lte",
+    "and", "or", "not", "len",
+];
+
+/// Custom helpers registered on every engine
+fn custom_helpers() -> Vec<(&'static str, Box<dyn HelperDef + Send + Sync>)> {
+    vec![
+        ("color", Box::new(color_helper)),
+        ("bg", Box::new(bg_helper)),
+        ("fg", Box::new(fg_helper)),
+        ("segment", Box::new(segment_helper)),
+        ("bold", Box::new(bold_helper)),
+        ("dim", Box::new(dim_helper)),
+        ("italic", Box::new(italic_helper)),
+        ("underline", Box::new(underline_helper)),
+        ("reset", Box::new(reset_helper)),
+        ("truncate", Box::new(truncate_helper)),
+        ("pad_left", Box::new(pad_left_helper)),
+        ("pad_right", Box::new(pad_right_helper)),
+        ("center", Box::new(center_helper)),
+        ("line", Box::new(line_helper)),
+        ("format_path", Box::new(FormatPathHelper(Arc::default()))),
+        ("format_time", Box::new(format_time_helper)),
+        ("format_duration", Box::new(format_duration_helper)),
+        ("format_number", Box::new(format_number_helper)),
+        ("format_bytes", Box::new(format_bytes_helper)),
+        ("fill_space", Box::new(fill_space_helper)),
+        ("gradient", Box::new(gradient_helper)),
+        ("color_scale", Box::new(color_scale_helper)),
+        ("lighten", Box::new(lighten_helper)),
+        ("darken", Box::new(darken_helper)),
+        ("mix", Box::new(mix_helper)),
+        ("contrast", Box::new(contrast_helper)),
+        ("env", Box::new(EnvHelper(Arc::default()))),
+        ("env_set", Box::new(EnvSetHelper(Arc::default()))),
+    ]
 }
 
 impl TemplateEngine {
@@ -25,37 +107,53 @@ impl TemplateEngine {
         let mut handlebars = Handlebars::new();
 
         // Register custom helpers
-        handlebars.register_helper("color", Box::new(color_helper));
-        handlebars.register_helper("bg", Box::new(bg_helper));
-        handlebars.register_helper("fg", Box::new(fg_helper));
-        handlebars.register_helper("segment", Box::new(segment_helper));
-        handlebars.register_helper("bold", Box::new(bold_helper));
-        handlebars.register_helper("dim", Box::new(dim_helper));
-        handlebars.register_helper("italic", Box::new(italic_helper));
-        handlebars.register_helper("underline", Box::new(underline_helper));
-        handlebars.register_helper("reset", Box::new(reset_helper));
-        handlebars.register_helper("truncate", Box::new(truncate_helper));
-        handlebars.register_helper("pad_left", Box::new(pad_left_helper));
-        handlebars.register_helper("pad_right", Box::new(pad_right_helper));
-        handlebars.register_helper("center", Box::new(center_helper));
-        handlebars.register_helper("line", Box::new(line_helper));
-        handlebars.register_helper("format_path", Box::new(format_path_helper));
-        handlebars.register_helper("format_time", Box::new(format_time_helper));
-        handlebars.register_helper("fill_space", Box::new(fill_space_helper));
-        handlebars.register_helper("gradient", Box::new(gradient_helper));
+        let mut helper_names: Vec<&'static str> = BUILTIN_HELPERS.to_vec();
+        for (name, helper) in custom_helpers() {
+            handlebars.register_helper(name, helper);
+            helper_names.push(name);
+        }
 
         // Disable HTML escaping for terminal output
         handlebars.register_escape_fn(handlebars::no_escape);
 
         Ok(Self {
-            handlebars,
+            handlebars: RefCell::new(handlebars),
             context_data: HashMap::new(),
             colors: HashMap::new(),
             symbols: HashMap::new(),
             segments: HashMap::new(),
+            pipeline: None,
+            helper_names,
+            processed: BTreeMap::new(),
+            uncompiled: RefCell::new(HashMap::new()),
+            path_substitutions: Arc::default(),
         })
     }
 
+    /// Create an engine from a compiled theme, skipping preprocessing
+    /// Templates only compiled cleanly into the cache, so Handlebars compiles
+    /// each one when it is first rendered rather than up front
+    pub fn from_compiled(theme: &CompiledTheme) -> Result<Self> {
+        let mut engine = Self::new()?;
+        engine.set_strict(theme.strict);
+        engine.set_env_allowlist(theme.env_allow.clone());
+        engine.set_path_substitutions(PathSubstitutions::new(&theme.path_substitutions)?);
+        engine.processed = theme.templates.clone();
+        engine.uncompiled = RefCell::new(theme.templates.clone().into_iter().collect());
+        engine.pipeline = theme.pipeline.clone();
+        Ok(engine)
+    }
+
+    /// Preprocessed templates and segment lists, for building a [`CompiledTheme`]
+    pub fn compiled_parts(&self) -> (BTreeMap<String, String>, Option<SegmentPipeline>) {
+        (self.processed.clone(), self.pipeline.clone())
+    }
+
+    /// Whether `name` is a registered helper rather than a context variable
+    pub fn is_helper(&self, name: &str) -> bool {
+        self.helper_names.contains(&name)
+    }
+
     /// Set colors for template preprocessing
     pub fn set_colors(&mut self, colors: HashMap<String, String>) {
         self.colors = colors;
@@ -80,12 +178,49 @@ impl TemplateEngine {
         preprocessor.add_segments(self.segments.clone());
         let processed = preprocessor.preprocess(template)?;
 
+        self.register_preprocessed(name, &processed)
+    }
+
+    /// Register a template that has already been preprocessed
+    fn register_preprocessed(&mut self, name: &str, processed: &str) -> Result<()> {
+        self.uncompiled.get_mut().remove(name);
         self.handlebars
-            .register_template_string(name, &processed)
+            .get_mut()
+            .register_template_string(name, processed)
             .with_context(|| format!("Failed to register template: {}", name))?;
+        self.processed
+            .insert(name.to_string(), processed.to_string());
         Ok(())
     }
 
+    /// Set up declarative segment lists, registering each listed segment's content
+    /// Colors, symbols and segments must be set first
+    pub fn set_segment_layout(&mut self, layout: &SegmentConfig) -> Result<()> {
+        let preprocessor =
+            TemplatePreprocessor::with_symbols(self.colors.clone(), self.symbols.clone());
+        let pipeline = SegmentPipeline::new(layout, &self.segments, &preprocessor)?;
+
+        for segment in pipeline.segments() {
+            self.register_template(&segment.template, &segment.content)?;
+        }
+        self.pipeline = Some(pipeline);
+        Ok(())
+    }
+
+    /// Render the segment lists against the current context
+    /// Returns `segments_left`, `segments_center` and `segments_right` outputs
+    pub fn render_segment_lists(&self) -> Result<Vec<(String, String)>> {
+        match &self.pipeline {
+            Some(pipeline) => pipeline.render(|template| self.render(template)),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Whether a template with this name has been registered
+    pub fn has_template(&self, name: &str) -> bool {
+        self.uncompiled.borrow().contains_key(name) || self.handlebars.borrow().has_template(name)
+    }
+
     /// Load templates from a TOML configuration
     pub fn load_templates_from_config(&mut self, config_str: &str) -> Result<()> {
         let config: TemplateConfig = toml::from_str(config_str)?;
@@ -93,10 +228,70 @@ impl TemplateEngine {
         for (name, template) in config.templates {
             self.register_template(&name, &template)?;
         }
+        for (name, template) in config.vars {
+            self.register_var(&name, &template)?;
+        }
 
         Ok(())
     }
 
+    /// Register a `[vars]` entry, rendered into the context as `name`
+    pub fn register_var(&mut self, name: &str, template: &str) -> Result<()> {
+        if context::is_context_variable(name) {
+            bail!(
+                "Variable '{}' in [vars] would shadow the built-in context variable",
+                name
+            );
+        }
+        self.register_template(&format!("{}{}", VAR_PREFIX, name), template)
+    }
+
+    /// Evaluate the `[vars]` entries against the current context
+    /// Vars start out empty and are re-rendered until no value changes, so
+    /// one var can use another regardless of their order. Values are trimmed,
+    /// and "true", "false" and whole numbers become JSON booleans and numbers
+    /// so `{{#if}}` treats "false" and "0" as false.
+    pub fn render_vars(&self) -> Result<Vec<(String, Value)>> {
+        let names: Vec<&str> = self
+            .processed
+            .keys()
+            .filter_map(|name| name.strip_prefix(VAR_PREFIX))
+            .collect();
+
+        let mut context = self.context_data.clone();
+        for name in &names {
+            context.insert(name.to_string(), json!(""));
+        }
+
+        // An acyclic chain of n vars settles within n passes
+        for _ in 0..names.len() {
+            let mut changed = false;
+            for name in &names {
+                let template = format!("{}{}", VAR_PREFIX, name);
+                self.compile_on_demand(&template)?;
+                let value = self
+                    .handlebars
+                    .borrow()
+                    .render(&template, &context)
+                    .map_err(|e| self.explain_render_error(e))
+                    .with_context(|| format!("Failed to render var: {}", name))?;
+                let value = var_value(value.trim());
+                if context.get(*name) != Some(&value) {
+                    context.insert(name.to_string(), value);
+                    changed = true;
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+
+        Ok(names
+            .into_iter()
+            .map(|name| (name.to_string(), context[name].clone()))
+            .collect())
+    }
+
     /// Set context data
     pub fn set_context(&mut self, data: HashMap<String, Value>) {
         self.context_data = data;
@@ -107,15 +302,78 @@ impl TemplateEngine {
         self.context_data.insert(key.to_string(), value);
     }
 
+    /// Let the `env` and `env_set` helpers read variables matching these patterns
+    pub fn set_env_allowlist(&mut self, patterns: Vec<String>) {
+        let allowlist = Arc::new(EnvAllowlist::new(patterns));
+        let handlebars = self.handlebars.get_mut();
+        handlebars.register_helper("env", Box::new(EnvHelper(allowlist.clone())));
+        handlebars.register_helper("env_set", Box::new(EnvSetHelper(allowlist)));
+    }
+
+    /// Use `[directory.substitutions]` in `format_path` and [`Self::substitute_path`]
+    pub fn set_path_substitutions(&mut self, substitutions: PathSubstitutions) {
+        let substitutions = Arc::new(substitutions);
+        self.path_substitutions = substitutions.clone();
+        self.handlebars
+            .get_mut()
+            .register_helper("format_path", Box::new(FormatPathHelper(substitutions)));
+    }
+
+    /// Apply the directory substitutions to a path, as used for `pwd_short`
+    pub fn substitute_path(&self, path: &str) -> String {
+        self.path_substitutions.apply(path).path
+    }
+
+    /// Enable Handlebars strict mode, making undefined variables an error
+    pub fn set_strict(&mut self, strict: bool) {
+        self.handlebars.get_mut().set_strict_mode(strict);
+    }
+
     /// Render a template
     pub fn render(&self, template_name: &str) -> Result<String> {
+        self.compile_on_demand(template_name)?;
+
         let result = self
             .handlebars
+            .borrow()
             .render(template_name, &self.context_data)
+            .map_err(|e| self.explain_render_error(e))
             .with_context(|| format!("Failed to render template: {}", template_name))?;
         Ok(result)
     }
 
+    /// Compile a template loaded from a compiled theme the first time it renders
+    fn compile_on_demand(&self, name: &str) -> Result<()> {
+        let Some(source) = self.uncompiled.borrow_mut().remove(name) else {
+            return Ok(());
+        };
+        self.handlebars
+            .borrow_mut()
+            .register_template_string(name, &source)
+            .with_context(|| format!("Failed to register template: {}", name))?;
+        Ok(())
+    }
+
+    /// Add the closest context keys to strict mode "missing variable" errors
+    fn explain_render_error(&self, error: RenderError) -> anyhow::Error {
+        let RenderErrorReason::MissingVariable(Some(name)) = error.reason() else {
+            return error.into();
+        };
+
+        let mut message = format!("Undefined variable '{}'", name);
+        if let (Some(line), Some(column)) = (error.line_no, error.column_no) {
+            message.push_str(&format!(" at line {}, column {}", line, column));
+        }
+
+        let candidates = context_keys(&self.context_data);
+        let suggestions = closest_keys(name, &candidates, 3);
+        if !suggestions.is_empty() {
+            message.push_str(&format!(". Did you mean: {}?", suggestions.join(", ")));
+        }
+
+        anyhow::anyhow!(message)
+    }
+
     /// Render a template string directly (with preprocessing for simplified syntax)
     pub fn render_string(&self, template: &str) -> Result<String> {
         // Preprocess the template to convert simplified syntax
@@ -127,12 +385,62 @@ impl TemplateEngine {
 
         let result = self
             .handlebars
+            .borrow()
             .render_template(&processed, &self.context_data)
             .with_context(|| "Failed to render template string")?;
         Ok(result)
     }
 }
 
+/// Context keys available to templates, including one level of nested
+/// objects (`colors.red`) so dotted paths get suggestions too
+fn context_keys(context: &HashMap<String, Value>) -> Vec<String> {
+    let mut keys = Vec::new();
+    for (key, value) in context {
+        keys.push(key.clone());
+        if let Value::Object(map) = value {
+            keys.extend(map.keys().map(|sub| format!("{}.{}", key, sub)));
+        }
+    }
+    keys
+}
+
+/// The keys closest to `name` by edit distance, best first
+fn closest_keys(name: &str, candidates: &[String], limit: usize) -> Vec<String> {
+    // Allow roughly one typo per three characters
+    let max_distance = (name.chars().count() / 3).max(2);
+
+    let mut scored: Vec<(usize, &String)> = candidates
+        .iter()
+        .map(|key| (edit_distance(name, key), key))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .collect();
+    scored.sort();
+
+    scored
+        .into_iter()
+        .take(limit)
+        .map(|(_, key)| key.clone())
+        .collect()
+}
+
+/// Levenshtein distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
 /// Template configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TemplateConfig {
@@ -140,10 +448,14 @@ pub struct TemplateConfig {
     pub templates: HashMap<String, String>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub colors: Option<HashMap<String, String>>,
+    /// Symbol strings, plus per-set override tables like `[symbols.ascii]`
     #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub symbols: Option<HashMap<String, String>>,
+    pub symbols: Option<HashMap<String, toml::Value>>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub segment: Option<HashMap<String, SegmentDefinition>>,
+    /// Computed variables, rendered into the context before the templates
+    #[serde(default)]
+    pub vars: BTreeMap<String, String>,
 }
 
 /// Segment definition for reusable prompt components
@@ -466,104 +778,18 @@ fn line_helper(
     Ok(())
 }
 
-/// Format path helper: {{format_path path "mode"}}
-/// Modes: "last", "first:N", "depth:N", "ellipsis", "full"
-/// Examples:
-///   {{format_path pwd "last"}} -> …/current-dir
-///   {{format_path pwd "first:1"}} -> ~/p/z/z-p-r
-///   {{format_path pwd "depth:2"}} -> ~/zuper-shell-prompt/zush-prompt-rust
-///   {{format_path pwd "ellipsis"}} -> ~/…/zush-prompt-rust
-fn format_path_helper(
-    h: &Helper,
-    _: &Handlebars,
-    _: &Context,
-    _: &mut RenderContext,
-    out: &mut dyn Output,
-) -> HelperResult {
-    let path = h.param(0).and_then(|v| v.value().as_str()).unwrap_or("");
-    let mode = h
-        .param(1)
-        .and_then(|v| v.value().as_str())
-        .unwrap_or("full");
-
-    let result = match mode {
-        "last" => {
-            // Only last segment with ellipsis
-            if let Some(last) = path.split('/').last() {
-                if path.contains('/') {
-                    format!("…/{}", last)
-                } else {
-                    last.to_string()
-                }
-            } else {
-                path.to_string()
-            }
-        }
-        mode if mode.starts_with("first:") => {
-            // First N characters of each segment
-            let n = mode
-                .strip_prefix("first:")
-                .and_then(|s| s.parse::<usize>().ok())
-                .unwrap_or(1);
-
-            let segments: Vec<&str> = path.split('/').collect();
-            let formatted: Vec<String> = segments
-                .iter()
-                .enumerate()
-                .map(|(i, seg)| {
-                    // Don't abbreviate ~ or the last segment
-                    if seg == &"~" || i == segments.len() - 1 || seg.is_empty() {
-                        seg.to_string()
-                    } else {
-                        seg.chars().take(n).collect()
-                    }
-                })
-                .collect();
-            formatted.join("/")
-        }
-        mode if mode.starts_with("depth:") => {
-            // Only deepest N directories
-            let n = mode
-                .strip_prefix("depth:")
-                .and_then(|s| s.parse::<usize>().ok())
-                .unwrap_or(2);
-
-            let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
-            if segments.len() <= n {
-                // Showing all segments, keep original path
-                path.to_string()
-            } else {
-                // Truncating: preserve ~ if present, otherwise use ellipsis
-                let start_idx = segments.len() - n;
-                let prefix = if segments[0] == "~" { "~" } else { "…" };
-                format!("{}/{}", prefix, segments[start_idx..].join("/"))
-            }
-        }
-        "ellipsis" => {
-            // Base + ellipsis + current (show first and last, hide middle)
-            let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
-            if segments.len() <= 2 {
-                path.to_string()
-            } else {
-                let first = segments[0];
-                let last = segments[segments.len() - 1];
-                format!("{}/…/{}", first, last)
-            }
-        }
-        _ => path.to_string(), // "full" or unknown mode
-    };
-
-    write!(out, "{}", result)?;
-    Ok(())
-}
-
-/// Format time helper: {{format_time time "format_string"}}
-/// Format string uses % placeholders for time parts and style tags for formatting:
-///   %H - hours (00-23)
-///   %M - minutes (00-59)
-///   %S - seconds (00-59)
-///   %I - hours 12-hour format (01-12)
-///   %p - AM/PM
+/// Format time helper: {{format_time zone "format_string"}}
+/// The format string supports the full chrono strftime syntax (%Y, %m, %d, %H, %M,
+/// %S, %I, %p, %a, %b, %Z, ...) plus style tags for formatting.
+///
+/// The first parameter selects what to format:
+///   - a time zone: "UTC", "local" or an IANA name like "America/New_York"
+///   - a number: a Unix timestamp in seconds, formatted in local time
+///   - anything else (e.g. the `time` variable): the current local time
+///
+/// Hash parameters override the above:
+///   tz="Europe/Berlin" - time zone to display
+///   epoch=1700000000   - Unix timestamp (seconds) to format instead of now
 ///
 /// Style tags (can wrap any text including time parts):
 ///   (bold)...(/bold) - bold text
@@ -574,8 +800,10 @@ fn format_path_helper(
 /// Examples:
 ///   {{format_time time "(bold)%H:%M:%S(/bold)"}} -> bold timestamp
 ///   {{format_time time "(dim)%H(/dim):%M:%S"}} -> dim hour, normal minutes/seconds
-///   {{format_time time "(dim)%H(/dim):(bold)%M:%S(/bold)"}} -> dim hour, bold min:sec
 ///   {{format_time time "%I:%M %p"}} -> 12-hour format with AM/PM
+///   {{format_time "America/New_York" "NYC %H:%M"}} -> world clock
+///   {{format_time "UTC" "%Y-%m-%d" epoch=git_commit_ts}} -> arbitrary timestamp
+///   {{format_time git_commit_ts "%d %b"}} -> timestamp as the first parameter
 fn format_time_helper(
     h: &Helper,
     _: &Handlebars,
@@ -583,25 +811,41 @@ fn format_time_helper(
     _: &mut RenderContext,
     out: &mut dyn Output,
 ) -> HelperResult {
-    use chrono::{Local, Timelike};
+    use crate::format::{format_time, instant_from_epoch, is_time_zone, parse_timestamp};
 
-    // Get the time string from context (if provided) or use current time
-    let _time_str = h.param(0).and_then(|v| v.value().as_str()).unwrap_or("");
+    let first = h.param(0).map(|v| v.value());
     let format_str = h
         .param(1)
         .and_then(|v| v.value().as_str())
         .unwrap_or("%H:%M:%S");
 
-    // Get current time for formatting
-    let now = Local::now();
+    // Timestamp: epoch= hash, or a first parameter that isn't a zone
+    // (Unix seconds, RFC 3339 or a time like the `time` variable); otherwise
+    // now, which also covers an empty or 12-hour `time` from the shell
+    let epoch = h.hash_get("epoch").and_then(|v| param_as_f64(v.value()));
+    let instant = match (epoch, first) {
+        (Some(secs), _) => instant_from_epoch(secs),
+        (None, Some(Value::Number(n))) => n.as_f64().and_then(instant_from_epoch),
+        (None, Some(Value::String(s))) if !is_time_zone(s) => {
+            Some(parse_timestamp(s).unwrap_or_else(chrono::Utc::now))
+        }
+        _ => Some(chrono::Utc::now()),
+    };
+    let Some(instant) = instant else {
+        return Ok(());
+    };
+
+    // Zone: tz= hash, or a first parameter that names a zone; otherwise local
+    let zone = h
+        .hash_get("tz")
+        .and_then(|v| v.value().as_str())
+        .or_else(|| first.and_then(|v| v.as_str()).filter(|s| is_time_zone(s)))
+        .unwrap_or("local");
 
-    // Replace time placeholders
-    let mut result = format_str.to_string();
-    result = result.replace("%H", &format!("{:02}", now.hour()));
-    result = result.replace("%M", &format!("{:02}", now.minute()));
-    result = result.replace("%S", &format!("{:02}", now.second()));
-    result = result.replace("%I", &format!("{:02}", now.hour12().1));
-    result = result.replace("%p", if now.hour() < 12 { "AM" } else { "PM" });
+    let mut result = match format_time(instant, zone, format_str) {
+        Some(formatted) => formatted,
+        None => return Ok(()),
+    };
 
     // Process style tags (both long and short forms)
     result = result.replace("(bold)", "\x1b[1m");
@@ -628,6 +872,104 @@ fn format_time_helper(
     Ok(())
 }
 
+/// Format duration helper: {{format_duration milliseconds "style" precision}}
+/// Styles:
+///   "compact" (default) - 850ms, 2.5s, 62m3s
+///   "hms"               - 1h 02m 03s
+///   "short"             - 1.2s, 4.5m, 1.2h
+/// Precision (optional) sets the decimals of fractional seconds/units.
+///
+/// Examples:
+///   {{format_duration execution_time_ms}} -> 12.3s
+///   {{format_duration execution_time_ms "hms"}} -> 1h 02m 03s
+///   {{format_duration execution_time_ms "short" 2}} -> 12.35s
+fn format_duration_helper(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    use crate::format::{format_duration, DurationStyle};
+
+    let ms = match h.param(0).and_then(|v| param_as_f64(v.value())) {
+        Some(v) => v,
+        None => return Ok(()),
+    };
+    let style: DurationStyle = h
+        .param(1)
+        .and_then(|v| v.value().as_str())
+        .and_then(|s| s.parse().ok())
+        .unwrap_or_default();
+    let precision = h
+        .param(2)
+        .and_then(|v| v.value().as_u64())
+        .map(|p| p as usize)
+        .unwrap_or_else(|| style.default_precision());
+
+    write!(out, "{}", format_duration(ms, style, precision))?;
+    Ok(())
+}
+
+/// Format number helper: {{format_number value "mode" precision}}
+/// Modes: "grouped" (default) -> 1,234,567 and "si" -> 1.2M
+fn format_number_helper(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    use crate::format::{format_grouped, format_si};
+
+    let value = match h.param(0).and_then(|v| param_as_f64(v.value())) {
+        Some(v) => v,
+        None => return Ok(()),
+    };
+    let mode = h
+        .param(1)
+        .and_then(|v| v.value().as_str())
+        .unwrap_or("grouped");
+    let precision = h
+        .param(2)
+        .and_then(|v| v.value().as_u64())
+        .map(|p| p as usize);
+
+    let result = match mode {
+        "si" => format_si(value, precision.unwrap_or(1)),
+        _ => format_grouped(value, precision.unwrap_or(0)),
+    };
+
+    write!(out, "{}", result)?;
+    Ok(())
+}
+
+/// Format bytes helper: {{format_bytes value "units" precision}}
+/// Units: "si" (default) -> 1.5 kB and "iec" -> 1.5 KiB
+fn format_bytes_helper(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    use crate::format::{format_bytes, ByteUnits};
+
+    let bytes = match h.param(0).and_then(|v| param_as_f64(v.value())) {
+        Some(v) => v,
+        None => return Ok(()),
+    };
+    let units: ByteUnits = h
+        .param(1)
+        .and_then(|v| v.value().as_str())
+        .and_then(|s| s.parse().ok())
+        .unwrap_or_default();
+    let precision = h.param(2).and_then(|v| v.value().as_u64()).unwrap_or(1) as usize;
+
+    write!(out, "{}", format_bytes(bytes, units, precision))?;
+    Ok(())
+}
+
 /// Fill space helper: {{fill_space terminal_width left_content right_content offset}}
 /// Calculates how much space is needed between left and right content to fill the terminal width.
 /// This is useful for creating full-width backgrounds with content on both sides.
@@ -673,80 +1015,251 @@ fn fill_space_helper(
     Ok(())
 }
 
-/// Gradient helper: {{gradient "start_color" "end_color" "text"}}
-/// Creates a color gradient across the text from start color to end color
-/// Each character gets a progressively interpolated color
-///
-/// Examples:
-///   {{gradient "#1abc9c" "#7aa2f7" "username"}} -> gradient from teal to blue
-///   {{gradient colors.teal colors.blue "username"}} -> using color variables
-fn gradient_helper(
-    h: &Helper,
-    _: &Handlebars,
-    _: &Context,
-    _: &mut RenderContext,
+/// Gradient helper: colors text character by character through two or more stops
+/// Inline: {{gradient "#ff0000" "#00ff00" "#0000ff" "text"}} (last parameter is the text)
+/// Block:  {{#gradient colors.red colors.blue}}...{{/gradient}} colors the rendered
+///         content, skipping style codes when counting positions
+/// Options: space="oklab"|"hsl"|"rgb" (default rgb), bg=true for a background
+///          gradient, width=N pads the text with spaces (e.g. full-width bars)
+fn gradient_helper<'reg, 'rc>(
+    h: &Helper<'rc>,
+    r: &'reg Handlebars<'reg>,
+    ctx: &'rc Context,
+    rc: &mut RenderContext<'reg, 'rc>,
     out: &mut dyn Output,
 ) -> HelperResult {
     let params = h.params();
 
-    if params.len() < 3 {
+    // The inline form takes its text from the last parameter
+    let (color_count, mut text) = match h.template() {
+        Some(template) => {
+            let mut buffer = StringOutput::new();
+            template.render(r, ctx, rc, &mut buffer)?;
+            (params.len(), buffer.into_string()?)
+        }
+        None if params.len() >= 3 => {
+            let text = params[params.len() - 1].value().render();
+            (params.len() - 1, text)
+        }
+        None => return Ok(()),
+    };
+
+    let mut stops = Vec::new();
+    for i in 0..color_count {
+        match color_param(h, i, ctx) {
+            Some(color) => stops.push(color),
+            None => return Ok(()),
+        }
+    }
+
+    let space = match h.hash_get("space").and_then(|v| v.value().as_str()) {
+        Some(name) => match ColorSpace::parse(name) {
+            Some(space) => space,
+            None => return Ok(()),
+        },
+        None => ColorSpace::default(),
+    };
+    let Ok(gradient) = Gradient::new(stops, space) else {
         return Ok(());
+    };
+
+    if let Some(width) = h.hash_get("width").and_then(|v| param_as_f64(v.value())) {
+        let visible = crate::buffer::TerminalBuffer::visible_width(&text);
+        let width = width.max(0.0) as usize;
+        if visible < width {
+            text.push_str(&" ".repeat(width - visible));
+        }
     }
 
-    // Get start and end colors
-    let start_hex = params[0].value().as_str().unwrap_or("#ffffff");
-    let end_hex = params[1].value().as_str().unwrap_or("#ffffff");
-    let text = params[2].value().as_str().unwrap_or("");
+    if text.is_empty() {
+        return Ok(());
+    }
 
-    // Parse colors
-    let start_color = match Color::from_hex(start_hex) {
-        Ok(c) => c,
-        Err(_) => return Ok(()),
+    let background = h
+        .hash_get("bg")
+        .map(|v| v.value().as_bool().unwrap_or(false))
+        .unwrap_or(false);
+    write!(out, "{}", gradient.apply(&text, background))?;
+
+    // Inline gradients reset everything as before; blocks only undo their own
+    // color so styles around them keep applying
+    if h.template().is_some() {
+        write!(out, "{}", if background { "\x1b[49m" } else { "\x1b[39m" })?;
+    } else {
+        write!(out, "\x1b[0m")?;
+    }
+
+    Ok(())
+}
+
+/// Color scale helper: {{color_scale value "0:green 1000:yellow 5000:red"}}
+/// Maps a numeric value to a color by interpolating between stops.
+/// Stop colors can be hex codes or names from the theme's [colors] section.
+///
+/// With two parameters the hex color is returned, so it composes with other helpers;
+/// with a third text parameter the text is written in that color (auto-reset).
+///
+/// Examples:
+///   {{fg (color_scale execution_time_ms "0:green 1000:yellow 5000:red")}}
+///   {{color_scale jobs "0:fg_dim 5:red" jobs}}
+fn color_scale_helper(
+    h: &Helper,
+    _: &Handlebars,
+    ctx: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let value = match h.param(0).and_then(|v| param_as_f64(v.value())) {
+        Some(v) => v,
+        None => return Ok(()),
     };
-    let end_color = match Color::from_hex(end_hex) {
-        Ok(c) => c,
+    let spec = h.param(1).and_then(|v| v.value().as_str()).unwrap_or("");
+
+    let scale = match ColorScale::parse(spec, |c| resolve_context_color(c, ctx)) {
+        Ok(s) => s,
         Err(_) => return Ok(()),
     };
+    let color = scale.at(value);
 
-    // Get RGB components
-    let (r1, g1, b1) = (start_color.r, start_color.g, start_color.b);
-    let (r2, g2, b2) = (end_color.r, end_color.g, end_color.b);
+    if let Some(text) = h.param(2) {
+        // Allow numbers as the text, e.g. {{color_scale jobs "..." jobs}}
+        let text = match text.value() {
+            Value::String(s) => s.clone(),
+            other => other.to_string(),
+        };
+        write!(out, "{}{}\x1b[0m", color.to_ansi_fg(), text)?;
+    } else {
+        write!(out, "{}", color.to_hex())?;
+    }
 
-    // Calculate gradient for each character
-    let chars: Vec<char> = text.chars().collect();
-    let char_count = chars.len();
+    Ok(())
+}
 
-    if char_count == 0 {
-        return Ok(());
+/// Lighten helper: {{lighten color amount}}
+/// Returns the hex color moved toward white by amount (0.0-1.0 or "20%")
+///
+/// Example:
+///   {{bg (lighten colors.bg_git 0.2)}}
+fn lighten_helper(
+    h: &Helper,
+    _: &Handlebars,
+    ctx: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    if let (Some(color), Some(amount)) = (color_param(h, 0, ctx), amount_param(h, 1)) {
+        write!(out, "{}", color.lighten(amount).to_hex())?;
     }
+    Ok(())
+}
 
-    for (i, ch) in chars.iter().enumerate() {
-        let t = if char_count == 1 {
-            0.0
-        } else {
-            i as f32 / (char_count - 1) as f32
-        };
-
-        // Interpolate RGB values
-        let r = (r1 as f32 + (r2 as f32 - r1 as f32) * t) as u8;
-        let g = (g1 as f32 + (g2 as f32 - g1 as f32) * t) as u8;
-        let b = (b1 as f32 + (b2 as f32 - b1 as f32) * t) as u8;
+/// Darken helper: {{darken color amount}}
+/// Returns the hex color moved toward black by amount (0.0-1.0 or "30%")
+///
+/// Example:
+///   {{fg (darken colors.bg_git 0.3)}}
+fn darken_helper(
+    h: &Helper,
+    _: &Handlebars,
+    ctx: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    if let (Some(color), Some(amount)) = (color_param(h, 0, ctx), amount_param(h, 1)) {
+        write!(out, "{}", color.darken(amount).to_hex())?;
+    }
+    Ok(())
+}
 
-        // Write character with interpolated color
-        let color = Color::new(r, g, b);
-        write!(out, "{}{}", color.to_ansi_fg(), ch)?;
+/// Mix helper: {{mix color_a color_b ratio}}
+/// Returns the hex color blended from a toward b (ratio 0.0 = a, 1.0 = b)
+///
+/// Example:
+///   {{bg (mix colors.bg_user colors.bg_pwd 0.5)}}
+fn mix_helper(
+    h: &Helper,
+    _: &Handlebars,
+    ctx: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let ratio = amount_param(h, 2).unwrap_or(0.5);
+    if let (Some(a), Some(b)) = (color_param(h, 0, ctx), color_param(h, 1, ctx)) {
+        write!(out, "{}", a.mix(&b, ratio).to_hex())?;
     }
+    Ok(())
+}
 
-    // Reset color at the end
-    write!(out, "\x1b[0m")?;
+/// Contrast helper: {{contrast background [candidate...]}}
+/// Returns the most readable foreground for the background using WCAG
+/// relative luminance: black or white, or the best of the given candidates
+///
+/// Examples:
+///   {{color (contrast colors.bg_user) user}}
+///   {{fg (contrast colors.bg_git colors.fg_light colors.fg_dark)}}
+fn contrast_helper(
+    h: &Helper,
+    _: &Handlebars,
+    ctx: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let background = match color_param(h, 0, ctx) {
+        Some(c) => c,
+        None => return Ok(()),
+    };
+
+    let candidates: Vec<Color> = (1..h.params().len())
+        .filter_map(|i| color_param(h, i, ctx))
+        .collect();
+    let color = background
+        .best_contrast(&candidates)
+        .unwrap_or_else(|| background.contrast());
 
+    write!(out, "{}", color.to_hex())?;
     Ok(())
 }
 
+/// Read a helper parameter as a color (hex or theme color name)
+fn color_param(h: &Helper, index: usize, ctx: &Context) -> Option<Color> {
+    h.param(index)
+        .and_then(|v| v.value().as_str())
+        .and_then(|s| resolve_context_color(s, ctx))
+}
+
+/// Read a helper parameter as an amount: a number or a string like "30%"
+fn amount_param(h: &Helper, index: usize) -> Option<f32> {
+    let value = h.param(index)?.value();
+    match value.as_f64() {
+        Some(n) => Some(n as f32),
+        None => value.as_str().and_then(crate::color::parse_amount),
+    }
+}
+
+/// Read a helper parameter as a number (accepts numeric strings too)
+fn param_as_f64(value: &Value) -> Option<f64> {
+    value
+        .as_f64()
+        .or_else(|| value.as_str().and_then(|s| s.trim().parse().ok()))
+}
+
+/// Resolve a color argument: hex codes directly, otherwise a name
+/// looked up in the `colors` map of the render context
+fn resolve_context_color(name: &str, ctx: &Context) -> Option<Color> {
+    let name = name.trim();
+    if name.starts_with('#') {
+        return Color::from_hex(name).ok();
+    }
+    ctx.data()
+        .get("colors")
+        .and_then(|colors| colors.get(name))
+        .and_then(|v| v.as_str())
+        .and_then(|hex| Color::from_hex(hex).ok())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use serde_json::json;
 
     #[test]
     fn test_template_rendering() {
@@ -898,5 +1411,338 @@ mod tests {
         let result = engine.render("path_short_last").unwrap();
         assert_eq!(result, "…/documents");
         println!("Short path last: {}", result);
+
+        // fit: terminal_width minus the other content and reserve
+        engine.set_value("pwd", json!("~/projects/zuper-shell/zush"));
+        engine.set_value("terminal_width", json!(30));
+        engine.set_value("git_branch", json!("main"));
+        engine
+            .register_template(
+                "path_fit",
+                r##"{{format_path pwd "fit" git_branch reserve=6}}"##,
+            )
+            .unwrap();
+        assert_eq!(engine.render("path_fit").unwrap(), "~/p/zuper-shell/zush");
+    }
+
+    #[test]
+    fn test_color_scale_helper() {
+        let mut engine = TemplateEngine::new().unwrap();
+        engine.set_value("colors", json!({"green": "#00ff00", "red": "#ff0000"}));
+
+        engine
+            .register_template("hex", r##"{{color_scale ms "0:green 1000:red"}}"##)
+            .unwrap();
+        engine.set_value("ms", json!(0));
+        assert_eq!(engine.render("hex").unwrap(), "#00ff00");
+        engine.set_value("ms", json!(500));
+        assert_eq!(engine.render("hex").unwrap(), "#7f7f00");
+        engine.set_value("ms", json!(99999));
+        assert_eq!(engine.render("hex").unwrap(), "#ff0000");
+
+        // Composes with fg, and the preprocessor form expands to the same call
+        engine
+            .register_template("fg", r##"(fg scale:ms 0:#00ff00 1000:#ff0000)x(/fg)"##)
+            .unwrap();
+        assert_eq!(engine.render("fg").unwrap(), "\x1b[38;2;255;0;0mx\x1b[39m");
+
+        // Text form writes colored text with a reset
+        engine
+            .register_template("text", r##"{{color_scale ms "0:green 1000:red" ms}}"##)
+            .unwrap();
+        assert_eq!(
+            engine.render("text").unwrap(),
+            "\x1b[38;2;255;0;0m99999\x1b[0m"
+        );
+    }
+
+    /// Render each template and compare it with the expected output
+    fn assert_renders(engine: &mut TemplateEngine, cases: &[(&str, &str)]) {
+        for (i, (template, expected)) in cases.iter().enumerate() {
+            let name = format!("case{}", i);
+            engine.register_template(&name, template).unwrap();
+            assert_eq!(engine.render(&name).unwrap(), *expected, "{}", template);
+        }
+    }
+
+    #[test]
+    fn test_format_helpers() {
+        let mut engine = TemplateEngine::new().unwrap();
+        engine.set_value("ms", json!(3_723_000));
+        engine.set_value("secs", json!(12.3456789));
+
+        let cases = [
+            (r##"{{format_duration ms}}"##, "62m3s"),
+            (r##"{{format_duration ms "hms"}}"##, "1h 02m 03s"),
+            (r##"{{format_duration 1234 "short"}}"##, "1.2s"),
+            (r##"{{format_duration 12345.6789 "short" 2}}"##, "12.35s"),
+            (r##"{{format_number 1234567}}"##, "1,234,567"),
+            (r##"{{format_number 1234567 "si"}}"##, "1.2M"),
+            (r##"{{format_bytes 1536}}"##, "1.5 kB"),
+            (r##"{{format_bytes 1536 "iec"}}"##, "1.5 KiB"),
+            (r##"{{format_bytes "2048" "iec" 0}}"##, "2 KiB"),
+        ];
+
+        assert_renders(&mut engine, &cases);
+    }
+
+    #[test]
+    fn test_format_time_helper() {
+        let mut engine = TemplateEngine::new().unwrap();
+        engine.set_value("time", json!("12:34:56"));
+        engine.set_value("ts", json!(1_700_000_000));
+
+        let cases = [
+            (
+                r##"{{format_time "UTC" "%Y-%m-%d %H:%M" epoch=ts}}"##,
+                "2023-11-14 22:13",
+            ),
+            (
+                r##"{{format_time "America/New_York" "%H:%M" epoch=ts}}"##,
+                "17:13",
+            ),
+            (
+                r##"{{format_time time "%H" tz="Asia/Tokyo" epoch=ts}}"##,
+                "07",
+            ),
+            (
+                r##"{{format_time "UTC" "(b)%H(/b)" epoch=ts}}"##,
+                "\x1b[1m22\x1b[22m",
+            ),
+        ];
+
+        assert_renders(&mut engine, &cases);
+
+        // A non-zone first parameter is the timestamp to format
+        engine
+            .register_template("time", r##"{{format_time time "%H:%M"}}"##)
+            .unwrap();
+        assert_eq!(engine.render("time").unwrap(), "12:34");
+        engine
+            .register_template("first_ts", r##"{{format_time ts "%Y" tz="UTC"}}"##)
+            .unwrap();
+        assert_eq!(engine.render("first_ts").unwrap(), "2023");
+
+        // ...and anything that is neither a zone nor a timestamp means now
+        engine
+            .register_template("bad", r##"{{format_time "soon" "%Y"}}"##)
+            .unwrap();
+        let year = chrono::Local::now().format("%Y").to_string();
+        assert_eq!(engine.render("bad").unwrap(), year);
+        engine.set_value("time", json!("2:03 PM"));
+        assert_eq!(engine.render("bad").unwrap(), year);
+    }
+
+    #[test]
+    fn test_color_math_helpers() {
+        let mut engine = TemplateEngine::new().unwrap();
+        engine.set_value("colors", json!({"grey": "#646464", "light": "#eeeeee"}));
+
+        let cases = [
+            (r##"{{lighten "#000000" 0.2}}"##, "#333333"),
+            (r##"{{darken colors.grey 0.5}}"##, "#323232"),
+            (r##"{{darken "grey" "50%"}}"##, "#323232"),
+            (r##"{{mix "#000000" "#ffffff" 0.5}}"##, "#7f7f7f"),
+            (r##"{{contrast colors.grey}}"##, "#ffffff"),
+            (r##"{{contrast colors.light}}"##, "#000000"),
+            (
+                r##"{{contrast "#1a1b26" "#333333" colors.light}}"##,
+                "#eeeeee",
+            ),
+            (
+                r##"{{fg (darken colors.grey 0.5)}}"##,
+                "\x1b[38;2;50;50;50m",
+            ),
+        ];
+
+        assert_renders(&mut engine, &cases);
+    }
+
+    #[test]
+    fn test_gradient_stops_and_tag() {
+        let mut engine = TemplateEngine::new().unwrap();
+        engine.set_value("colors", json!({"red": "#ff0000", "blue": "#0000ff"}));
+
+        engine
+            .register_template(
+                "inline",
+                r##"{{gradient "#ff0000" "#00ff00" "#0000ff" "abc"}}"##,
+            )
+            .unwrap();
+        assert_eq!(
+            engine.render("inline").unwrap(),
+            "\x1b[38;2;255;0;0ma\x1b[38;2;0;255;0mb\x1b[38;2;0;0;255mc\x1b[0m"
+        );
+
+        engine
+            .register_template(
+                "bg",
+                r##"{{gradient colors.red colors.blue "ab" bg=true width=3}}"##,
+            )
+            .unwrap();
+        let output = engine.render("bg").unwrap();
+        assert!(output.starts_with("\x1b[48;2;255;0;0ma"));
+        assert!(output.contains("\x1b[48;2;0;0;255m "));
+
+        // Style codes inside the tag don't take up gradient steps
+        engine.set_colors(HashMap::from([
+            ("red".to_string(), "#ff0000".to_string()),
+            ("blue".to_string(), "#0000ff".to_string()),
+        ]));
+        engine
+            .register_template("tag", "(gradient red blue)(b)a(/b)b(/gradient)")
+            .unwrap();
+        assert_eq!(
+            engine.render("tag").unwrap(),
+            "\x1b[1m\x1b[38;2;255;0;0ma\x1b[22m\x1b[38;2;0;0;255mb\x1b[39m"
+        );
+    }
+
+    #[test]
+    fn test_env_helpers() {
+        std::env::set_var("ZUSH_TEST_KUBE", "prod-cluster");
+        let mut engine = TemplateEngine::new().unwrap();
+        let templates = [
+            ("value", r#"{{env "ZUSH_TEST_KUBE" "none"}}"#),
+            ("denied", r#"{{env "HOME" "none"}}"#),
+            ("missing", r#"{{env "ZUSH_TEST_UNSET"}}"#),
+            (
+                "set",
+                r#"{{#if (env_set "ZUSH_TEST_KUBE")}}yes{{else}}no{{/if}}"#,
+            ),
+        ];
+        for (name, template) in templates {
+            engine.register_template(name, template).unwrap();
+        }
+
+        // Nothing is readable until the config allows it
+        assert_eq!(engine.render("value").unwrap(), "none");
+        assert_eq!(engine.render("set").unwrap(), "no");
+
+        engine.set_env_allowlist(vec!["ZUSH_TEST_*".to_string()]);
+        assert_eq!(engine.render("value").unwrap(), "prod-cluster");
+        assert_eq!(engine.render("denied").unwrap(), "none");
+        assert_eq!(engine.render("missing").unwrap(), "");
+        assert_eq!(engine.render("set").unwrap(), "yes");
+    }
+
+    #[test]
+    fn test_vars() {
+        let mut engine = TemplateEngine::new().unwrap();
+        engine
+            .load_templates_from_config(
+                r#"
+[vars]
+label = "{{#if is_prod}}PROD {{user}}{{/if}}"
+is_prod = """
+{{#if (eq profile 'prod')}}true{{/if}}
+"""
+
+[templates]
+main = "{{#if is_prod}}!{{/if}}{{label}}"
+"#,
+            )
+            .unwrap();
+
+        engine.set_context(HashMap::from([
+            ("profile".to_string(), json!("prod")),
+            ("user".to_string(), json!("me")),
+        ]));
+        // label sorts first but still sees is_prod, and whitespace is trimmed
+        let vars = engine.render_vars().unwrap();
+        assert_eq!(
+            vars,
+            vec![
+                ("is_prod".to_string(), json!(true)),
+                ("label".to_string(), json!("PROD me")),
+            ]
+        );
+        for (name, value) in vars {
+            engine.set_value(&name, value);
+        }
+        assert_eq!(engine.render("main").unwrap(), "!PROD me");
+
+        engine.set_value("profile", json!("dev"));
+        assert_eq!(engine.render_vars().unwrap()[0].1, json!(""));
+
+        // "false" and "0" are falsy, other text stays a string
+        assert_eq!(var_value("false"), json!(false));
+        assert_eq!(var_value("0"), json!(0));
+        assert_eq!(var_value("007"), json!("007"));
+        assert_eq!(var_value("no"), json!("no"));
+
+        let err = engine.register_var("pwd", "x").unwrap_err();
+        assert!(err.to_string().contains("shadow"));
+    }
+
+    #[test]
+    fn test_from_compiled() {
+        let mut engine = TemplateEngine::new().unwrap();
+        engine.set_colors(HashMap::from([("red".to_string(), "#ff0000".to_string())]));
+        engine
+            .register_template("main", "(fg red){{user}}(/fg)")
+            .unwrap();
+        engine.set_strict(true);
+
+        let (templates, pipeline) = engine.compiled_parts();
+        assert_eq!(templates["main"], "\x1b[38;2;255;0;0m{{user}}\x1b[39m");
+
+        let theme = CompiledTheme {
+            strict: true,
+            templates,
+            pipeline,
+            ..Default::default()
+        };
+        let mut cached = TemplateEngine::from_compiled(&theme).unwrap();
+        assert!(cached.has_template("main"));
+        assert!(!cached.has_template("transient"));
+
+        cached.set_value("user", json!("me"));
+        assert_eq!(
+            cached.render("main").unwrap(),
+            "\x1b[38;2;255;0;0mme\x1b[39m"
+        );
+        // Strict mode carries over from the compiled theme
+        assert!(cached.render_string("{{nope}}").is_err());
+    }
+
+    #[test]
+    fn test_strict_mode_suggestions() {
+        let mut engine = TemplateEngine::new().unwrap();
+        engine.set_strict(true);
+        engine
+            .register_template("main", "{{#if git_dirty}}x{{/if}}{{git_brnach}}")
+            .unwrap();
+        let mut context = HashMap::new();
+        context.insert("git_branch".to_string(), json!("main"));
+        context.insert("git_behind".to_string(), json!(0));
+        context.insert("git_dirty".to_string(), json!(false));
+        context.insert("user".to_string(), json!("me"));
+        engine.set_context(context);
+
+        let error = format!("{:#}", engine.render("main").unwrap_err());
+        assert!(
+            error.contains("Undefined variable 'git_brnach'"),
+            "{}",
+            error
+        );
+        assert!(error.contains("Did you mean: git_branch"), "{}", error);
+        assert!(!error.contains("user"), "{}", error);
+
+        // Without strict mode the typo renders as empty
+        engine.set_strict(false);
+        assert_eq!(engine.render("main").unwrap(), "");
+    }
+
+    #[test]
+    fn test_closest_keys() {
+        let keys: Vec<String> = ["git_branch", "git_behind", "colors.red", "time"]
+            .iter()
+            .map(|k| k.to_string())
+            .collect();
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(closest_keys("colors.rde", &keys, 3), vec!["colors.red"]);
+        assert_eq!(closest_keys("git_bhind", &keys, 1), vec!["git_behind"]);
+        assert!(closest_keys("zzzzzz", &keys, 3).is_empty());
     }
 }
@@ -0,0 +1,480 @@
+//! Path display for `pwd_short` and the `format_path` helper
+//!
+//! `[directory.substitutions]` maps path prefixes or regexes to short labels,
+//! so `~/src/github.com/acme/api` can display as ` acme/api`. Substitutions
+//! run in a fixed order: the longest matching prefix first (at most one),
+//! then every `re:` pattern in key order, each on the previous result.
+
+use crate::buffer::TerminalBuffer;
+use anyhow::{Context as AnyhowContext, Result};
+use handlebars::{
+    Context, Handlebars, Helper, HelperDef, JsonRender, Output, RenderContext, RenderError,
+};
+use regex::Regex;
+use std::sync::Arc;
+
+/// Keys starting with this are regexes rather than path prefixes
+const REGEX_PREFIX: &str = "re:";
+
+/// Compiled `[directory.substitutions]`
+#[derive(Debug, Clone, Default)]
+pub struct PathSubstitutions {
+    /// (prefix, label), longest prefix first
+    prefixes: Vec<(String, String)>,
+    /// (pattern, replacement) in key order
+    patterns: Vec<(Regex, String)>,
+    home: Option<String>,
+}
+
+/// A path after substitution, remembering whether its first segment is a label
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubstitutedPath {
+    pub path: String,
+    /// The first segment came from a prefix label and should never be abbreviated
+    pub labeled: bool,
+}
+
+impl PathSubstitutions {
+    /// Build from (key, label) pairs, failing on invalid regexes
+    /// Prefixes starting with `~/` also match the same path under `$HOME`
+    pub fn new(entries: &[(String, String)]) -> Result<Self> {
+        let home = dirs::home_dir().map(|home| home.display().to_string());
+        Self::with_home(entries, home)
+    }
+
+    fn with_home(entries: &[(String, String)], home: Option<String>) -> Result<Self> {
+        let mut entries = entries.to_vec();
+        entries.sort();
+
+        let mut prefixes = Vec::new();
+        let mut patterns = Vec::new();
+        for (key, label) in entries {
+            match key.strip_prefix(REGEX_PREFIX) {
+                Some(pattern) => {
+                    let regex = Regex::new(pattern).with_context(|| {
+                        format!("Invalid regex '{}' in [directory.substitutions]", pattern)
+                    })?;
+                    patterns.push((regex, label));
+                }
+                None => prefixes.push((key.trim_end_matches('/').to_string(), label)),
+            }
+        }
+        // Stable sort keeps equal-length prefixes in key order
+        prefixes.sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+
+        Ok(Self {
+            prefixes,
+            patterns,
+            home,
+        })
+    }
+
+    /// Apply the substitutions to a path
+    pub fn apply(&self, path: &str) -> SubstitutedPath {
+        let mut result = SubstitutedPath {
+            path: path.to_string(),
+            labeled: false,
+        };
+
+        for (prefix, label) in &self.prefixes {
+            if let Some(rest) = self.strip_prefix(path, prefix) {
+                result = SubstitutedPath {
+                    path: format!("{}{}", label, rest),
+                    labeled: true,
+                };
+                break;
+            }
+        }
+
+        for (regex, replacement) in &self.patterns {
+            result.path = regex
+                .replace_all(&result.path, replacement.as_str())
+                .into_owned();
+        }
+
+        result
+    }
+
+    /// (repo name, subpath) when `path` is inside the work tree at `root`
+    /// `path` may be absolute or start with `~`
+    fn repo_relative(&self, path: &str, root: &str) -> Option<(String, String)> {
+        let root = root.trim_end_matches('/');
+        if root.is_empty() {
+            return None;
+        }
+        let name = root.rsplit('/').next()?.to_string();
+        let subpath = self.strip_prefix(path, root).or_else(|| {
+            let home = self.home.as_deref()?;
+            let root = format!("~{}", root.strip_prefix(home)?);
+            self.strip_prefix(path, &root)
+        })?;
+        Some((name, subpath.trim_start_matches('/').to_string()))
+    }
+
+    /// The rest of `path` after `prefix`, matching whole segments only
+    fn strip_prefix<'a>(&self, path: &'a str, prefix: &str) -> Option<&'a str> {
+        let expanded = match (prefix.strip_prefix('~'), &self.home) {
+            (Some(rest), Some(home)) => Some(format!("{}{}", home, rest)),
+            _ => None,
+        };
+
+        std::iter::once(prefix)
+            .chain(expanded.as_deref())
+            .find_map(|prefix| {
+                let rest = path.strip_prefix(prefix)?;
+                (rest.is_empty() || rest.starts_with('/')).then_some(rest)
+            })
+    }
+}
+
+/// Format a path for display
+/// Modes: "last", "first:N", "depth:N", "ellipsis", "full", "repo", "repo:N",
+/// "fit:N"
+/// A substituted label is kept whole like `~`. The repo modes need the work
+/// tree root and show the path as `<repo name>/<subpath>`, falling back to
+/// "full" outside a repository.
+pub fn format_path(
+    path: &str,
+    mode: &str,
+    substitutions: &PathSubstitutions,
+    repo_root: Option<&str>,
+) -> String {
+    if mode == "repo" || mode.starts_with("repo:") {
+        let relative = repo_root.and_then(|root| substitutions.repo_relative(path, root));
+        if let Some((name, subpath)) = relative {
+            return format_repo_path(&name, &subpath, mode);
+        }
+        return substitutions.apply(path).path;
+    }
+
+    if let Some(budget) = mode.strip_prefix("fit:") {
+        let budget = budget.parse().unwrap_or(usize::MAX);
+        let repo_depth = repo_root
+            .and_then(|root| substitutions.repo_relative(path, root))
+            .map(|(_, subpath)| subpath.split('/').filter(|s| !s.is_empty()).count());
+        return fit_path(&substitutions.apply(path), repo_depth, budget);
+    }
+
+    let SubstitutedPath { path, labeled } = substitutions.apply(path);
+    let path = path.as_str();
+    let is_root = |segment: &str, index: usize| index == 0 && (labeled || segment == "~");
+
+    match mode {
+        "last" => {
+            // Only last segment with ellipsis
+            if let Some(last) = path.split('/').next_back() {
+                if path.contains('/') {
+                    format!("…/{}", last)
+                } else {
+                    last.to_string()
+                }
+            } else {
+                path.to_string()
+            }
+        }
+        mode if mode.starts_with("first:") => {
+            // First N characters of each segment
+            let n = mode
+                .strip_prefix("first:")
+                .and_then(|s| s.parse::<usize>().ok())
+                .unwrap_or(1);
+
+            let segments: Vec<&str> = path.split('/').collect();
+            let formatted: Vec<String> = segments
+                .iter()
+                .enumerate()
+                .map(|(i, seg)| {
+                    // Don't abbreviate ~, a label or the last segment
+                    if is_root(seg, i) || i == segments.len() - 1 || seg.is_empty() {
+                        seg.to_string()
+                    } else {
+                        seg.chars().take(n).collect()
+                    }
+                })
+                .collect();
+            formatted.join("/")
+        }
+        mode if mode.starts_with("depth:") => {
+            // Only deepest N directories
+            let n = mode
+                .strip_prefix("depth:")
+                .and_then(|s| s.parse::<usize>().ok())
+                .unwrap_or(2);
+
+            let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+            if segments.len() <= n {
+                // Showing all segments, keep original path
+                path.to_string()
+            } else {
+                // Truncating: preserve ~ or a label if present, otherwise use ellipsis
+                let start_idx = segments.len() - n;
+                let prefix = if is_root(segments[0], 0) {
+                    segments[0]
+                } else {
+                    "…"
+                };
+                format!("{}/{}", prefix, segments[start_idx..].join("/"))
+            }
+        }
+        "ellipsis" => {
+            // Base + ellipsis + current (show first and last, hide middle)
+            let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+            if segments.len() <= 2 {
+                path.to_string()
+            } else {
+                let first = segments[0];
+                let last = segments[segments.len() - 1];
+                format!("{}/…/{}", first, last)
+            }
+        }
+        _ => path.to_string(), // "full" or unknown mode
+    }
+}
+
+/// Abbreviate segments from the root until the path fits in `budget` columns
+/// `~`, a substitution label, the repo root (`repo_depth` segments above the
+/// last) and the last segment are never abbreviated. When abbreviating every
+/// other segment isn't enough, they are collapsed into `…` from the root.
+fn fit_path(path: &SubstitutedPath, repo_depth: Option<usize>, budget: usize) -> String {
+    let fits = |segments: &[String]| TerminalBuffer::visible_width(&segments.join("/")) <= budget;
+
+    let mut segments: Vec<String> = path.path.split('/').map(str::to_string).collect();
+    if fits(&segments) {
+        return path.path.clone();
+    }
+
+    let last = segments.len() - 1;
+    let repo_index = repo_depth.and_then(|depth| last.checked_sub(depth));
+    let candidates: Vec<usize> = (0..last)
+        .filter(|&i| {
+            let segment = &segments[i];
+            let is_root = i == 0 && (path.labeled || segment == "~");
+            !segment.is_empty() && !is_root && Some(i) != repo_index
+        })
+        .collect();
+
+    for &i in &candidates {
+        segments[i] = abbreviate(&segments[i]);
+        if fits(&segments) {
+            return segments.join("/");
+        }
+    }
+
+    // Drop abbreviated segments from the root, one `…` per run of dropped ones
+    let mut collapsed = segments.clone();
+    for count in 1..=candidates.len() {
+        let dropped = &candidates[..count];
+        collapsed = Vec::new();
+        for (i, segment) in segments.iter().enumerate() {
+            if !dropped.contains(&i) {
+                collapsed.push(segment.clone());
+            } else if i == 0 || !dropped.contains(&(i - 1)) {
+                collapsed.push("…".to_string());
+            }
+        }
+        if fits(&collapsed) {
+            break;
+        }
+    }
+    collapsed.join("/")
+}
+
+/// First character of a segment, keeping the dot of hidden directories
+fn abbreviate(segment: &str) -> String {
+    let take = if segment.starts_with('.') { 2 } else { 1 };
+    segment.chars().take(take).collect()
+}
+
+/// `name/subpath`, keeping only the deepest N subpath segments for "repo:N"
+fn format_repo_path(name: &str, subpath: &str, mode: &str) -> String {
+    let segments: Vec<&str> = subpath.split('/').filter(|s| !s.is_empty()).collect();
+    let depth = mode
+        .strip_prefix("repo:")
+        .and_then(|n| n.parse::<usize>().ok())
+        .unwrap_or(segments.len());
+
+    let mut parts = vec![name];
+    if segments.len() > depth {
+        parts.push("…");
+    }
+    parts.extend(&segments[segments.len().saturating_sub(depth)..]);
+    parts.join("/")
+}
+
+/// Format path helper: {{format_path path "mode"}}
+/// Modes: "last", "first:N", "depth:N", "ellipsis", "full", "repo", "repo:N"
+/// Examples:
+///   {{format_path pwd "last"}} -> …/current-dir
+///   {{format_path pwd "first:1"}} -> ~/p/z/z-p-r
+///   {{format_path pwd "depth:2"}} -> ~/zuper-shell-prompt/zush-prompt-rust
+///   {{format_path pwd "ellipsis"}} -> ~/…/zush-prompt-rust
+///   {{format_path pwd "repo"}} -> zush/src/modules (using `git_root`)
+///   {{format_path pwd "repo:1"}} -> zush/…/modules
+///   {{format_path pwd "fit" git_branch reserve=10}} -> ~/p/zuper/zush-prompt-rust
+///     abbreviates from the root until the path fits in `terminal_width` minus
+///     the width of any further parameters (other content on the line) and
+///     `reserve` columns; "fit:N" uses a fixed budget of N columns
+pub(super) struct FormatPathHelper(pub(super) Arc<PathSubstitutions>);
+
+impl HelperDef for FormatPathHelper {
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        ctx: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+        out: &mut dyn Output,
+    ) -> Result<(), RenderError> {
+        let path = h.param(0).and_then(|v| v.value().as_str()).unwrap_or("");
+        let mode = h
+            .param(1)
+            .and_then(|v| v.value().as_str())
+            .unwrap_or("full");
+        let data = ctx.data();
+        let repo_root = data
+            .get("git_root")
+            .and_then(|v| v.as_str())
+            .filter(|root| !root.is_empty());
+
+        let mode = if mode == "fit" {
+            let terminal_width = data
+                .get("terminal_width")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(80) as usize;
+            let others: usize = h
+                .params()
+                .iter()
+                .skip(2)
+                .map(|p| TerminalBuffer::visible_width(&p.value().render()))
+                .sum();
+            let reserve = h
+                .hash_get("reserve")
+                .and_then(|v| v.value().as_u64())
+                .unwrap_or(0) as usize;
+            format!("fit:{}", terminal_width.saturating_sub(others + reserve))
+        } else {
+            mode.to_string()
+        };
+
+        out.write(&format_path(path, &mode, &self.0, repo_root))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn substitutions(entries: &[(&str, &str)]) -> PathSubstitutions {
+        let entries: Vec<(String, String)> = entries
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        PathSubstitutions::with_home(&entries, Some("/home/me".to_string())).unwrap()
+    }
+
+    #[test]
+    fn test_prefix_substitution() {
+        let subs = substitutions(&[
+            ("~/src/github.com", "gh"),
+            ("~/src/github.com/acme", " acme"),
+            ("/opt", "opt"),
+        ]);
+
+        // The longest prefix wins, whether the path is absolute or uses ~
+        let expected = SubstitutedPath {
+            path: " acme/api".to_string(),
+            labeled: true,
+        };
+        assert_eq!(subs.apply("~/src/github.com/acme/api"), expected);
+        assert_eq!(subs.apply("/home/me/src/github.com/acme/api"), expected);
+        assert_eq!(subs.apply("~/src/github.com/other").path, "gh/other");
+        assert_eq!(subs.apply("/opt").path, "opt");
+
+        // Prefixes only match whole segments
+        assert_eq!(subs.apply("/optional").path, "/optional");
+        assert!(!subs.apply("/optional").labeled);
+    }
+
+    #[test]
+    fn test_regex_runs_after_prefix_in_key_order() {
+        let subs = substitutions(&[
+            ("re:b-(\\w+)", "B$1"),
+            ("re:/services/", "/svc/"),
+            ("~/work", "W"),
+        ]);
+        // Prefix first, then "re:/services/" before "re:b-(\w+)"
+        assert_eq!(subs.apply("~/work/services/b-api").path, "W/svc/Bapi");
+
+        let entries = vec![("re:(".to_string(), "x".to_string())];
+        let err = PathSubstitutions::new(&entries).unwrap_err();
+        assert!(err.to_string().contains("Invalid regex"));
+    }
+
+    #[test]
+    fn test_format_path_keeps_label() {
+        let subs = substitutions(&[("~/src/github.com/acme", " acme")]);
+        let path = "~/src/github.com/acme/services/api";
+
+        let format = |path, mode| format_path(path, mode, &subs, None);
+
+        assert_eq!(format(path, "full"), " acme/services/api");
+        assert_eq!(format(path, "first:1"), " acme/s/api");
+        assert_eq!(format(path, "depth:1"), " acme/api");
+        assert_eq!(format(path, "last"), "…/api");
+        assert_eq!(format("~/other/deep/path", "first:1"), "~/o/d/path");
+    }
+
+    #[test]
+    fn test_repo_modes() {
+        let subs = substitutions(&[("~/src", "src")]);
+        let root = Some("/home/me/src/zush");
+        let format = |path, mode| format_path(path, mode, &subs, root);
+
+        assert_eq!(
+            format("/home/me/src/zush/src/modules", "repo"),
+            "zush/src/modules"
+        );
+        assert_eq!(format("~/src/zush/src/modules", "repo"), "zush/src/modules");
+        assert_eq!(format("/home/me/src/zush", "repo"), "zush");
+        assert_eq!(format("/home/me/src/zush/a/b/c", "repo:2"), "zush/…/b/c");
+        assert_eq!(format("/home/me/src/zush/a/b", "repo:2"), "zush/a/b");
+        assert_eq!(format("/home/me/src/zush/a", "repo:0"), "zush/…");
+
+        // Outside the repo (or with no repo) the path is shown in full
+        assert_eq!(format("/home/me/src/zushx", "repo"), "src/zushx");
+        assert_eq!(format_path("/tmp/x", "repo", &subs, None), "/tmp/x");
+    }
+
+    #[test]
+    fn test_fit_mode() {
+        let subs = substitutions(&[]);
+        let path = "~/projects/zuper-shell/zush-prompt-rust/src/modules";
+        let format =
+            |budget: usize, root| format_path(path, &format!("fit:{}", budget), &subs, root);
+
+        // Wide enough: unchanged
+        assert_eq!(format(60, None), path);
+        // Abbreviate from the root, one segment at a time
+        assert_eq!(
+            format(45, None),
+            "~/p/zuper-shell/zush-prompt-rust/src/modules"
+        );
+        assert_eq!(format(35, None), "~/p/z/zush-prompt-rust/src/modules");
+        assert_eq!(format(18, None), "~/p/z/z/s/modules");
+        // Still too wide: collapse abbreviated segments from the root
+        assert_eq!(format(15, None), "~/…/z/s/modules");
+        assert_eq!(format(1, None), "~/…/modules");
+
+        // The repo root is never abbreviated
+        let root = Some("/home/me/projects/zuper-shell/zush-prompt-rust");
+        assert_eq!(format(33, root), "~/p/z/zush-prompt-rust/s/modules");
+        assert_eq!(format(1, root), "~/…/zush-prompt-rust/…/modules");
+
+        // Hidden directories keep their dot
+        assert_eq!(
+            format_path("~/.config/zush/themes", "fit:16", &subs, None),
+            "~/.c/zush/themes"
+        );
+    }
+}
@@ -0,0 +1,339 @@
+//! Declarative powerline segment lists
+//!
+//! A theme lists segment names under `[segments]` (`left`, `center`, `right`)
+//! and defines each one as `[segments.<name>]` with `bg`, `fg` and `content`.
+//! Each segment's content is rendered on its own; empty segments are dropped
+//! and separator glyphs are inserted between the remaining backgrounds.
+
+use super::preprocessor::{SeparatorDirection, TemplatePreprocessor};
+use super::SegmentDef;
+use crate::buffer::TerminalBuffer;
+use crate::config::SegmentConfig;
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Default shape between segments when a segment has no `sep`
+const DEFAULT_SEPARATOR: &str = "sharp";
+
+/// A segment with colors and glyphs resolved to terminal output
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(super) struct ResolvedSegment {
+    /// Name of the registered template holding the segment content
+    pub(super) template: String,
+    /// Raw template content, registered as `template`
+    pub(super) content: String,
+    bg: Option<(u8, u8, u8)>,
+    fg: Option<(u8, u8, u8)>,
+    /// Glyph between this segment and the next (or the end cap when last)
+    separator: String,
+    /// Cap on the list's outer edge, from `left_cap`: before the first
+    /// segment of a left/center list, after the last one of a right list
+    start_cap: Option<String>,
+}
+
+/// One rendered list of segments (e.g. `segments_left`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SegmentList {
+    variable: String,
+    direction: SeparatorDirection,
+    segments: Vec<ResolvedSegment>,
+}
+
+/// Segment lists from a theme, ready to render against a context
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SegmentPipeline {
+    lists: Vec<SegmentList>,
+}
+
+impl SegmentPipeline {
+    /// Resolve every listed segment, failing on unknown names, colors or shapes
+    pub(super) fn new(
+        layout: &SegmentConfig,
+        definitions: &HashMap<String, SegmentDef>,
+        preprocessor: &TemplatePreprocessor,
+    ) -> Result<Self> {
+        // Right-aligned content points its separators back toward the left
+        let sides = [
+            ("segments_left", SeparatorDirection::Right, &layout.left),
+            ("segments_center", SeparatorDirection::Right, &layout.center),
+            ("segments_right", SeparatorDirection::Left, &layout.right),
+        ];
+
+        let mut lists = Vec::new();
+        for (variable, direction, names) in sides {
+            let mut segments = Vec::new();
+            for name in names {
+                let Some(def) = definitions.get(name) else {
+                    bail!(
+                        "Unknown segment '{}' in [segments] {}. Define it as [segments.{}]",
+                        name,
+                        variable.trim_start_matches("segments_"),
+                        name
+                    );
+                };
+                segments.push(Self::resolve(def, direction, preprocessor)?);
+            }
+            lists.push(SegmentList {
+                variable: variable.to_string(),
+                direction,
+                segments,
+            });
+        }
+
+        Ok(Self { lists })
+    }
+
+    fn resolve(
+        def: &SegmentDef,
+        direction: SeparatorDirection,
+        preprocessor: &TemplatePreprocessor,
+    ) -> Result<ResolvedSegment> {
+        let resolve = |color: &Option<String>| -> Result<Option<(u8, u8, u8)>> {
+            color
+                .as_deref()
+                .map(|c| preprocessor.resolve_color(c))
+                .transpose()
+        };
+
+        let separator = preprocessor
+            .get_separator_glyph(def.sep.as_deref().unwrap_or(DEFAULT_SEPARATOR), direction)?;
+        // The outer cap faces away from the segment it closes off
+        let cap_direction = match direction {
+            SeparatorDirection::Right => SeparatorDirection::Left,
+            SeparatorDirection::Left => SeparatorDirection::Right,
+        };
+        let start_cap = def
+            .left_cap
+            .as_deref()
+            .map(|shape| preprocessor.get_separator_glyph(shape, cap_direction))
+            .transpose()?;
+
+        Ok(ResolvedSegment {
+            template: format!("segment:{}", def.name),
+            content: def.content.clone(),
+            bg: resolve(&def.bg)?,
+            fg: resolve(&def.fg)?,
+            separator,
+            start_cap,
+        })
+    }
+
+    /// All segments, for registering their content templates
+    pub(super) fn segments(&self) -> impl Iterator<Item = &ResolvedSegment> {
+        self.lists.iter().flat_map(|list| list.segments.iter())
+    }
+
+    /// Render each list, with `render` producing a segment's content
+    /// Returns (context variable, output) pairs, empty lists render as ""
+    pub(super) fn render<F>(&self, render: F) -> Result<Vec<(String, String)>>
+    where
+        F: Fn(&str) -> Result<String>,
+    {
+        let mut outputs = Vec::new();
+        for list in &self.lists {
+            let mut visible = Vec::new();
+            for segment in &list.segments {
+                let content = render(&segment.template)?;
+                if TerminalBuffer::visible_width(content.trim()) > 0 {
+                    visible.push((segment, content));
+                }
+            }
+
+            let output = match list.direction {
+                SeparatorDirection::Right => assemble_right_pointing(&visible),
+                SeparatorDirection::Left => assemble_left_pointing(&visible),
+            };
+            outputs.push((list.variable.clone(), output));
+        }
+        Ok(outputs)
+    }
+}
+
+/// `[cap] seg sep seg sep ... seg end` with separators pointing right
+fn assemble_right_pointing(segments: &[(&ResolvedSegment, String)]) -> String {
+    let mut output = String::new();
+
+    for (i, (segment, content)) in segments.iter().enumerate() {
+        if i == 0 {
+            if let Some(cap) = &segment.start_cap {
+                push_glyph(&mut output, cap, segment.bg, None);
+            }
+        }
+
+        push_body(&mut output, segment, content);
+
+        // Separator takes this background as its color over the next background
+        let next_bg = segments.get(i + 1).and_then(|(next, _)| next.bg);
+        push_glyph(&mut output, &segment.separator, segment.bg, next_bg);
+    }
+
+    output
+}
+
+/// `sep seg sep seg ... seg` with separators pointing left, each opening its segment
+fn assemble_left_pointing(segments: &[(&ResolvedSegment, String)]) -> String {
+    let mut output = String::new();
+
+    for (i, (segment, content)) in segments.iter().enumerate() {
+        let previous_bg = i
+            .checked_sub(1)
+            .and_then(|p| segments.get(p))
+            .and_then(|(previous, _)| previous.bg);
+        push_glyph(&mut output, &segment.separator, segment.bg, previous_bg);
+        push_body(&mut output, segment, content);
+
+        if i + 1 == segments.len() {
+            if let Some(cap) = &segment.start_cap {
+                push_glyph(&mut output, cap, segment.bg, None);
+            }
+        }
+    }
+
+    output
+}
+
+fn push_body(output: &mut String, segment: &ResolvedSegment, content: &str) {
+    if let Some((r, g, b)) = segment.bg {
+        output.push_str(&format!("\x1b[48;2;{};{};{}m", r, g, b));
+    }
+    if let Some((r, g, b)) = segment.fg {
+        output.push_str(&format!("\x1b[38;2;{};{};{}m", r, g, b));
+    }
+    output.push_str(&format!(" {} ", content));
+    output.push_str("\x1b[39m\x1b[49m");
+}
+
+/// Draw a separator glyph in `fg` over `bg`, resetting both afterwards
+fn push_glyph(
+    output: &mut String,
+    glyph: &str,
+    fg: Option<(u8, u8, u8)>,
+    bg: Option<(u8, u8, u8)>,
+) {
+    if glyph.is_empty() {
+        return;
+    }
+    if let Some((r, g, b)) = bg {
+        output.push_str(&format!("\x1b[48;2;{};{};{}m", r, g, b));
+    }
+    if let Some((r, g, b)) = fg {
+        output.push_str(&format!("\x1b[38;2;{};{};{}m", r, g, b));
+    }
+    output.push_str(glyph);
+    output.push_str("\x1b[39m\x1b[49m");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pipeline(layout: SegmentConfig) -> SegmentPipeline {
+        let colors = HashMap::from([
+            ("blue".to_string(), "#0000ff".to_string()),
+            ("green".to_string(), "#00ff00".to_string()),
+        ]);
+        let preprocessor = TemplatePreprocessor::new(colors);
+        let definitions = HashMap::from([
+            (
+                "user".to_string(),
+                SegmentDef::new("user".to_string(), "{{user}}".to_string())
+                    .with_bg("blue".to_string())
+                    .with_left_cap("pill".to_string()),
+            ),
+            (
+                "git".to_string(),
+                SegmentDef::new("git".to_string(), "{{git_branch}}".to_string())
+                    .with_bg("green".to_string()),
+            ),
+            (
+                "time".to_string(),
+                SegmentDef::new("time".to_string(), "{{time}}".to_string())
+                    .with_bg("#ff0000".to_string())
+                    .with_sep("slant".to_string()),
+            ),
+        ]);
+        SegmentPipeline::new(&layout, &definitions, &preprocessor).unwrap()
+    }
+
+    fn layout(left: &[&str], right: &[&str]) -> SegmentConfig {
+        SegmentConfig {
+            left: left.iter().map(|s| s.to_string()).collect(),
+            center: Vec::new(),
+            right: right.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    fn render(pipeline: &SegmentPipeline, values: &[(&str, &str)]) -> HashMap<String, String> {
+        let values: HashMap<&str, &str> = values.iter().cloned().collect();
+        pipeline
+            .render(|template| Ok(values.get(template).unwrap_or(&"").to_string()))
+            .unwrap()
+            .into_iter()
+            .collect()
+    }
+
+    const BLUE_BG: &str = "\x1b[48;2;0;0;255m";
+    const BLUE_FG: &str = "\x1b[38;2;0;0;255m";
+    const GREEN_BG: &str = "\x1b[48;2;0;255;0m";
+    const GREEN_FG: &str = "\x1b[38;2;0;255;0m";
+    const RED_BG: &str = "\x1b[48;2;255;0;0m";
+    const RED_FG: &str = "\x1b[38;2;255;0;0m";
+    const RESET: &str = "\x1b[39m\x1b[49m";
+
+    #[test]
+    fn test_left_transitions_and_caps() {
+        let pipeline = pipeline(layout(&["user", "git"], &[]));
+        let output = render(
+            &pipeline,
+            &[("segment:user", "me"), ("segment:git", "main")],
+        );
+
+        let expected = format!(
+            "{BLUE_FG}\u{e0b6}{RESET}{BLUE_BG} me {RESET}{GREEN_BG}{BLUE_FG}\u{e0b0}{RESET}{GREEN_BG} main {RESET}{GREEN_FG}\u{e0b0}{RESET}"
+        );
+        assert_eq!(output["segments_left"], expected);
+        assert_eq!(output["segments_right"], "");
+    }
+
+    #[test]
+    fn test_empty_segments_are_skipped() {
+        let pipeline = pipeline(layout(&["user", "git", "time"], &[]));
+        let output = render(
+            &pipeline,
+            &[
+                ("segment:user", "me"),
+                ("segment:git", "  "),
+                ("segment:time", "12:00"),
+            ],
+        );
+
+        // user transitions straight into time, which ends with its slant
+        let expected = format!(
+            "{BLUE_FG}\u{e0b6}{RESET}{BLUE_BG} me {RESET}{RED_BG}{BLUE_FG}\u{e0b0}{RESET}{RED_BG} 12:00 {RESET}{RED_FG}\u{e0bc}{RESET}"
+        );
+        assert_eq!(output["segments_left"], expected);
+    }
+
+    #[test]
+    fn test_right_side_points_left() {
+        let pipeline = pipeline(layout(&[], &["git", "time"]));
+        let output = render(
+            &pipeline,
+            &[("segment:git", "main"), ("segment:time", "12:00")],
+        );
+
+        let expected = format!(
+            "{GREEN_FG}\u{e0b2}{RESET}{GREEN_BG} main {RESET}{GREEN_BG}{RED_FG}\u{e0be}{RESET}{RED_BG} 12:00 {RESET}"
+        );
+        assert_eq!(output["segments_right"], expected);
+    }
+
+    #[test]
+    fn test_unknown_segment() {
+        let preprocessor = TemplatePreprocessor::new(HashMap::new());
+        let err = SegmentPipeline::new(&layout(&["nope"], &[]), &HashMap::new(), &preprocessor)
+            .unwrap_err();
+        assert!(err.to_string().contains("Unknown segment 'nope'"));
+    }
+}
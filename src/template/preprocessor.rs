@@ -1,6 +1,8 @@
 #![allow(dead_code)]
 
+use crate::color::{Color, ColorScale, ColorSpace};
 use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
 /// Preprocessor for simplified template syntax
@@ -222,7 +224,7 @@ impl TemplatePreprocessor {
 
     /// Parse segment parameters from the opening tag
     /// Format: "name" bg="color" fg="color" sep="shape" left_cap="shape"
-    fn parse_segment_params(&self, params: &str) -> Result<SegmentDef> {
+    pub(super) fn parse_segment_params(&self, params: &str) -> Result<SegmentDef> {
         let params = params.trim();
 
         // Extract name (first quoted string)
@@ -366,7 +368,7 @@ impl TemplatePreprocessor {
     }
 
     /// Get the separator symbol for a shape name
-    fn get_separator_symbol(&self, shape: &str) -> Result<String> {
+    pub(super) fn get_separator_symbol(&self, shape: &str) -> Result<String> {
         let symbol = match shape {
             "sharp" | "triangle" => "@segment_separator",
             "pill" | "round" => "@pill_left",
@@ -381,6 +383,40 @@ impl TemplatePreprocessor {
         Ok(symbol.to_string())
     }
 
+    /// Get the separator glyph for a shape, pointing in `direction`
+    /// Right-pointing glyphs close a segment, left-pointing ones open it.
+    /// A theme's `segment_separator` symbol replaces the right-pointing sharp glyph.
+    pub(super) fn get_separator_glyph(
+        &self,
+        shape: &str,
+        direction: SeparatorDirection,
+    ) -> Result<String> {
+        // Validates the shape name
+        let symbol = self.get_separator_symbol(shape)?;
+        if symbol.is_empty() {
+            return Ok(String::new());
+        }
+
+        if direction == SeparatorDirection::Right && symbol == "@segment_separator" {
+            if let Some(custom) = self.symbols.get("segment_separator") {
+                return Ok(custom.clone());
+            }
+        }
+
+        // Nerd Font flame glyphs are named for the side of the segment they sit on
+        let name = match (shape, direction) {
+            ("sharp" | "triangle", SeparatorDirection::Right) => "triangle_right",
+            ("sharp" | "triangle", SeparatorDirection::Left) => "triangle_left",
+            ("pill" | "round", SeparatorDirection::Right) => "pill_right",
+            ("pill" | "round", SeparatorDirection::Left) => "pill_left",
+            ("slant", SeparatorDirection::Right) => "slant_right",
+            ("slant", SeparatorDirection::Left) => "slant_left",
+            (_, SeparatorDirection::Right) => "flame_left",
+            (_, SeparatorDirection::Left) => "flame_right",
+        };
+        Self::resolve_symbol(name)
+    }
+
     /// Process @symbol_name shortcuts, replacing them with the actual symbol characters
     /// Theme symbols take precedence over built-in symbols
     fn process_symbol_shortcuts(&self, template: &str) -> Result<String> {
@@ -514,7 +550,10 @@ impl TemplatePreprocessor {
 
     /// Parse a style tag starting at position i
     /// Returns (tag, next_position) or None if not a valid tag
-    fn parse_style_tag(chars: &[char], start: usize) -> Result<Option<(StyleTag, usize)>> {
+    pub(super) fn parse_style_tag(
+        chars: &[char],
+        start: usize,
+    ) -> Result<Option<(StyleTag, usize)>> {
         if chars[start] != '(' {
             return Ok(None);
         }
@@ -554,6 +593,7 @@ impl TemplatePreprocessor {
             "fg",
             "bg",
             "sym",
+            "gradient",
         ];
         if !valid_styles.contains(&name.as_str()) {
             return Ok(None);
@@ -564,10 +604,17 @@ impl TemplatePreprocessor {
             i += 1;
         }
 
-        // Parse arguments (everything until closing paren)
+        // Parse arguments (everything until the matching closing paren,
+        // so color functions like darken(bg,0.3) can be nested inside)
         let mut args = String::new();
         if !is_closing {
-            while i < chars.len() && chars[i] != ')' {
+            let mut depth = 0;
+            while i < chars.len() && (chars[i] != ')' || depth > 0) {
+                match chars[i] {
+                    '(' => depth += 1,
+                    ')' => depth -= 1,
+                    _ => {}
+                }
                 args.push(chars[i]);
                 i += 1;
             }
@@ -602,6 +649,9 @@ impl TemplatePreprocessor {
             "underline" | "u" => Ok("\x1b[4m".to_string()),
             "fg" => {
                 if let Some(ref args) = tag.args {
+                    if let Some(scale) = args.strip_prefix("scale:") {
+                        return self.scale_expression("fg", scale);
+                    }
                     let color = self.resolve_color(args)?;
                     Ok(format!("\x1b[38;2;{};{};{}m", color.0, color.1, color.2))
                 } else {
@@ -610,12 +660,22 @@ impl TemplatePreprocessor {
             }
             "bg" => {
                 if let Some(ref args) = tag.args {
+                    if let Some(scale) = args.strip_prefix("scale:") {
+                        return self.scale_expression("bg", scale);
+                    }
                     let color = self.resolve_color(args)?;
                     Ok(format!("\x1b[48;2;{};{};{}m", color.0, color.1, color.2))
                 } else {
                     bail!("bg tag requires color argument")
                 }
             }
+            "gradient" => {
+                if let Some(ref args) = tag.args {
+                    self.gradient_expression(args)
+                } else {
+                    bail!("gradient tag requires at least two colors")
+                }
+            }
             "sym" => {
                 if let Some(ref args) = tag.args {
                     Self::resolve_symbol(args)
@@ -637,16 +697,95 @@ impl TemplatePreprocessor {
             "fg" => Ok("\x1b[39m".to_string()),         // Reset foreground
             "bg" => Ok("\x1b[49m".to_string()),         // Reset background
             "sym" => Ok("".to_string()),                // Symbols don't need closing
+            "gradient" => Ok("{{/gradient}}".to_string()), // Closes the block helper
             _ => bail!("Unknown style tag: {}", name),
         }
     }
 
+    /// Build a runtime color scale from `(fg scale:var 0:green 1000:red)`
+    /// The value is only known at render time, so this emits a Handlebars
+    /// `color_scale` call with stop colors already resolved to hex
+    pub(super) fn scale_expression(&self, helper: &str, args: &str) -> Result<String> {
+        let (variable, stops) = args
+            .trim()
+            .split_once(char::is_whitespace)
+            .ok_or_else(|| anyhow::anyhow!("{} scale requires a variable and stops, e.g. (fg scale:execution_time_ms 0:green 1000:red)", helper))?;
+
+        if variable.is_empty()
+            || !variable
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '_' || c == '.')
+        {
+            bail!("Invalid scale variable '{}'", variable);
+        }
+
+        let mut resolved = Vec::new();
+        for stop in stops.split_whitespace() {
+            let (value, color) = stop.split_once(':').ok_or_else(|| {
+                anyhow::anyhow!("Invalid scale stop '{}', expected value:color", stop)
+            })?;
+            let (r, g, b) = self.resolve_color(color)?;
+            resolved.push(format!("{}:#{:02x}{:02x}{:02x}", value, r, g, b));
+        }
+        let spec = resolved.join(" ");
+
+        // Validate stop values now rather than failing silently at render time
+        ColorScale::parse(&spec, |c| Color::from_hex(c).ok())?;
+
+        Ok(format!(
+            "{{{{{} (color_scale {} \"{}\")}}}}",
+            helper, variable, spec
+        ))
+    }
+
+    /// Build a gradient block from `(gradient #a #b #c space=oklab bg=true)`
+    /// The content is only known at render time, so this opens a Handlebars
+    /// `gradient` block with the stop colors already resolved to hex
+    pub(super) fn gradient_expression(&self, args: &str) -> Result<String> {
+        let mut colors = Vec::new();
+        let mut options = Vec::new();
+
+        for token in split_top_level_whitespace(args) {
+            if let Some((key, value)) = token.split_once('=').filter(|_| !token.contains('(')) {
+                match key {
+                    "space" => {
+                        if ColorSpace::parse(value).is_none() {
+                            bail!("Unknown gradient space '{}'. Use: rgb, oklab, hsl", value);
+                        }
+                        options.push(format!("space=\"{}\"", value));
+                    }
+                    "bg" => options.push(format!("bg={}", value == "true")),
+                    _ => bail!("Unknown gradient option '{}'. Use: space, bg", key),
+                }
+            } else {
+                let (r, g, b) = self.resolve_color(token)?;
+                colors.push(format!("\"#{:02x}{:02x}{:02x}\"", r, g, b));
+            }
+        }
+
+        if colors.len() < 2 {
+            bail!("gradient tag requires at least two colors, e.g. (gradient #ff0000 #0000ff)");
+        }
+
+        colors.extend(options);
+        Ok(format!("{{{{#gradient {}}}}}", colors.join(" ")))
+    }
+
     /// Resolve a color from a string (hex code or named color reference)
     /// For hex: returns (r, g, b) tuple
     /// For named color: looks up in the colors HashMap and resolves to RGB
-    fn resolve_color(&self, color_str: &str) -> Result<(u8, u8, u8)> {
+    /// For color functions: lighten(c,0.2), darken(c,0.3), mix(a,b,0.5), contrast(c)
+    pub(super) fn resolve_color(&self, color_str: &str) -> Result<(u8, u8, u8)> {
         let trimmed = color_str.trim();
 
+        if trimmed.contains('(') {
+            let color = Color::parse_expression(trimmed, &|name: &str| {
+                self.resolve_color(name)
+                    .map(|(r, g, b)| Color::new(r, g, b))
+            })?;
+            return Ok((color.r, color.g, color.b));
+        }
+
         // Check if it's a hex color (#ffffff)
         if trimmed.starts_with('#') {
             if trimmed.len() != 7 {
@@ -692,12 +831,49 @@ impl TemplatePreprocessor {
     }
 }
 
+/// Split on whitespace outside parentheses, so color functions like
+/// `mix(red, blue, 0.5)` stay one token
+fn split_top_level_whitespace(args: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut depth = 0;
+    let mut start = None;
+
+    for (i, c) in args.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            c if c.is_whitespace() && depth == 0 => {
+                if let Some(s) = start.take() {
+                    tokens.push(&args[s..i]);
+                }
+                continue;
+            }
+            _ => {}
+        }
+        if start.is_none() {
+            start = Some(i);
+        }
+    }
+    if let Some(s) = start {
+        tokens.push(&args[s..]);
+    }
+
+    tokens
+}
+
+/// Which way a powerline separator glyph points
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SeparatorDirection {
+    Right,
+    Left,
+}
+
 /// Represents a style tag like (bold) or (fg #ff0000)
 #[derive(Debug, Clone)]
-struct StyleTag {
-    name: String,
-    args: Option<String>,
-    is_closing: bool,
+pub(super) struct StyleTag {
+    pub(super) name: String,
+    pub(super) args: Option<String>,
+    pub(super) is_closing: bool,
 }
 
 #[cfg(test)]
@@ -848,6 +1024,80 @@ mod tests {
         assert_eq!(result2, result3);
         println!("All aliases produce same result: {:?}", result1);
     }
+
+    #[test]
+    fn test_fg_scale() {
+        let mut colors = HashMap::new();
+        colors.insert("green".to_string(), "#00ff00".to_string());
+        colors.insert("red".to_string(), "#ff0000".to_string());
+        let mut preprocessor = TemplatePreprocessor::new(colors);
+
+        let input = "(fg scale:execution_time_ms 0:green 5000:red){{execution_time_ms}}(/fg)";
+        let result = preprocessor.preprocess(input).unwrap();
+        assert_eq!(
+            result,
+            "{{fg (color_scale execution_time_ms \"0:#00ff00 5000:#ff0000\")}}{{execution_time_ms}}\x1b[39m"
+        );
+
+        let result = preprocessor
+            .preprocess("(bg scale:jobs 0:#000000 10:#ffffff)x(/bg)")
+            .unwrap();
+        assert!(result.starts_with("{{bg (color_scale jobs \"0:#000000 10:#ffffff\")}}"));
+    }
+
+    #[test]
+    fn test_fg_scale_invalid() {
+        let mut preprocessor = TemplatePreprocessor::new(HashMap::new());
+        assert!(preprocessor.preprocess("(fg scale:x)a(/fg)").is_err());
+        assert!(preprocessor
+            .preprocess("(fg scale:x 0:nope)a(/fg)")
+            .is_err());
+        assert!(preprocessor
+            .preprocess("(fg scale:x abc:#ffffff)a(/fg)")
+            .is_err());
+    }
+
+    #[test]
+    fn test_color_functions() {
+        let mut colors = HashMap::new();
+        colors.insert("bg_git".to_string(), "#646464".to_string());
+        let mut preprocessor = TemplatePreprocessor::new(colors);
+
+        let result = preprocessor
+            .preprocess("(fg darken(bg_git,0.5))x(/fg)")
+            .unwrap();
+        assert_eq!(result, "\x1b[38;2;50;50;50mx\x1b[39m");
+
+        let result = preprocessor
+            .preprocess("(bg mix(bg_git, #000000, 0.5))(fg contrast(bg_git))x(/fg)(/bg)")
+            .unwrap();
+        assert!(result.starts_with("\x1b[48;2;50;50;50m\x1b[38;2;255;255;255mx"));
+
+        assert!(preprocessor
+            .preprocess("(fg darken(nope,0.5))x(/fg)")
+            .is_err());
+    }
+
+    #[test]
+    fn test_gradient_tag() {
+        let colors = HashMap::from([("red".to_string(), "#ff0000".to_string())]);
+        let mut preprocessor = TemplatePreprocessor::new(colors);
+
+        let output = preprocessor
+            .preprocess("(gradient red #00ff00 #0000ff space=oklab bg=true)hi(/gradient)")
+            .unwrap();
+        assert_eq!(
+            output,
+            r##"{{#gradient "#ff0000" "#00ff00" "#0000ff" space="oklab" bg=true}}hi{{/gradient}}"##
+        );
+
+        assert!(preprocessor
+            .preprocess("(gradient red)x(/gradient)")
+            .is_err());
+        assert!(preprocessor
+            .preprocess("(gradient red #0000ff space=lab)x(/gradient)")
+            .is_err());
+    }
 }
 
 #[cfg(test)]
@@ -5,6 +5,7 @@
 //! This module provides utility functions for extracting colors, symbols,
 //! and segments from TOML configuration strings.
 
+use crate::config::SegmentConfig;
 use crate::template::SegmentDef;
 use serde_json::{json, Value};
 use std::collections::HashMap;
@@ -67,6 +68,16 @@ impl TomlParser {
                         result.insert(key.clone(), unicode_parser(str_value));
                     }
                 }
+
+                // Per-set overrides, e.g. [symbols.ascii], win for the active set
+                let set = crate::symbols::active_set().name();
+                if let Some(overrides) = table.get(set).and_then(|v| v.as_table()) {
+                    for (key, value) in overrides {
+                        if let Some(str_value) = value.as_str() {
+                            result.insert(key.clone(), unicode_parser(str_value));
+                        }
+                    }
+                }
             }
         }
 
@@ -109,6 +120,19 @@ impl TomlParser {
         result
     }
 
+    /// Extract the declarative segment lists (`left`/`center`/`right` under [segments])
+    /// Returns None when the theme lists no segments
+    pub fn extract_segment_layout(&self) -> Option<SegmentConfig> {
+        let table = self.parsed.as_ref()?.get("segments")?.clone();
+        let layout: SegmentConfig = table.try_into().ok()?;
+
+        if layout.left.is_empty() && layout.center.is_empty() && layout.right.is_empty() {
+            None
+        } else {
+            Some(layout)
+        }
+    }
+
     /// Parse a single segment definition from TOML
     fn parse_segment(name: &str, data: &toml::Value) -> Option<SegmentDef> {
         let props = data.as_table()?;
@@ -226,6 +250,26 @@ sep = "sharp"
         assert_eq!(symbols.get("branch"), Some(&"".to_string()));
     }
 
+    #[test]
+    fn test_extract_symbols_set_overrides() {
+        let toml = r#"
+[symbols]
+arrow = ">"
+branch = "b"
+
+[symbols.nerd]
+branch = "n"
+
+[symbols.ascii]
+branch = "git:"
+"#;
+        // Only the active set's table (nerd by default) applies
+        let symbols = TomlParser::new(Some(toml)).extract_symbols(|s| s.to_string());
+        assert_eq!(symbols.get("arrow"), Some(&">".to_string()));
+        assert_eq!(symbols.get("branch"), Some(&"n".to_string()));
+        assert!(!symbols.contains_key("ascii"));
+    }
+
     #[test]
     fn test_extract_segments() {
         let parser = TomlParser::new(Some(TEST_TOML));
@@ -237,6 +281,29 @@ sep = "sharp"
         assert_eq!(status.content, "ok");
     }
 
+    #[test]
+    fn test_extract_segment_layout() {
+        let parser = TomlParser::new(Some(TEST_TOML));
+        assert!(parser.extract_segment_layout().is_none());
+
+        let toml = r##"
+[segments]
+left = ["status", "user"]
+right = ["time"]
+
+[segments.status]
+content = "ok"
+"##;
+        let parser = TomlParser::new(Some(toml));
+        let layout = parser.extract_segment_layout().unwrap();
+        assert_eq!(layout.left, vec!["status", "user"]);
+        assert!(layout.center.is_empty());
+        assert_eq!(layout.right, vec!["time"]);
+
+        // Segment definitions still parse alongside the lists
+        assert!(parser.extract_segments().contains_key("status"));
+    }
+
     #[test]
     fn test_apply_overrides() {
         let parser = TomlParser::new(Some(TEST_TOML));
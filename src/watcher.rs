@@ -0,0 +1,578 @@
+//! Long-lived git status watcher for very large work trees (`ZUSH_GIT_WATCH=1`)
+//!
+//! Instead of rescanning the whole work tree in a background worker after
+//! every change, one watcher process per work tree keeps the set of changed
+//! paths up to date from inotify events and rewrites the status cache after
+//! each burst of events. Only the paths that changed are asked about again;
+//! a full rescan happens when HEAD, refs or the index move, or when whole
+//! directories appear or vanish. Ignored paths are skipped using the repo's
+//! own `.gitignore` rules, both for events and when choosing which
+//! directories to watch, so trees like `target/` or `node_modules/` cost no
+//! inotify watches. The watcher exits once no prompt has asked for it for
+//! `ZUSH_GIT_WATCH_IDLE` seconds (10 minutes by default).
+
+use anyhow::{Context, Result};
+use git2::{Repository, Status};
+use notify::event::{CreateKind, ModifyKind, RemoveKind};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::git::{self, GitStatus};
+
+/// How long to keep collecting events before updating the cache
+const DEBOUNCE: Duration = Duration::from_millis(50);
+
+/// How often to check the heartbeat while nothing happens
+const POLL: Duration = Duration::from_secs(5);
+
+/// Watch the work tree at `root` until prompts stop asking for it
+pub fn run(root: &Path, cache_path: &Path, signal_file: Option<&Path>) -> Result<()> {
+    let lock_path = git::watch_lock_path(cache_path);
+    git::write_pid_file(&lock_path)?;
+
+    let result = watch(root, cache_path, &lock_path, signal_file);
+    if result.is_err() {
+        // Fall back to the background worker for a while instead of
+        // respawning the watcher on every prompt
+        let _ = fs::write(git::watch_failed_path(cache_path), "");
+    }
+    if owns_lock(&lock_path) {
+        let _ = fs::remove_file(&lock_path);
+        let _ = fs::remove_file(git::watch_pending_path(cache_path));
+    }
+    result
+}
+
+fn watch(
+    root: &Path,
+    cache_path: &Path,
+    lock_path: &Path,
+    signal_file: Option<&Path>,
+) -> Result<()> {
+    let mut state = RepoWatch::open(root)?;
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx).context("Failed to start watcher")?;
+    for dir in state.dirs_to_watch(root) {
+        watch_dir(&mut watcher, &dir)?;
+    }
+    // HEAD, the index and refs; objects and logs are only noise
+    watcher.watch(&state.git_dir, RecursiveMode::NonRecursive)?;
+    if state.common_dir != state.git_dir {
+        watcher.watch(&state.common_dir, RecursiveMode::NonRecursive)?;
+    }
+    watcher.watch(&state.common_dir.join("refs"), RecursiveMode::Recursive)?;
+
+    state.rescan()?;
+    git::write_status_cache(cache_path, &state.status())?;
+    if let Some(signal) = signal_file {
+        let _ = git::touch_signal_file(signal);
+    }
+    signal_pending(cache_path);
+
+    let idle = std::env::var("ZUSH_GIT_WATCH_IDLE")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(Duration::from_secs(600));
+
+    loop {
+        if !owns_lock(lock_path) || heartbeat_age(lock_path) > idle {
+            return Ok(());
+        }
+
+        let first = match rx.recv_timeout(POLL) {
+            Ok(event) => event,
+            Err(mpsc::RecvTimeoutError::Timeout) => continue,
+            Err(mpsc::RecvTimeoutError::Disconnected) => return Ok(()),
+        };
+        let mut events = vec![first];
+        let deadline = Instant::now() + DEBOUNCE;
+        while let Ok(event) = rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            events.push(event);
+        }
+
+        // Taken before looking at the changes, so anything that moves the
+        // index meanwhile makes the cache stale rather than wrongly fresh
+        let fingerprint = git::repo_fingerprint(root).unwrap_or_default();
+        for dir in state.new_dirs(&events) {
+            for dir in state.dirs_to_watch(&dir) {
+                watch_dir(&mut watcher, &dir)?;
+            }
+        }
+        if state.apply(&events) {
+            state.fingerprint = fingerprint;
+            git::write_status_cache(cache_path, &state.status())?;
+            signal_pending(cache_path);
+        }
+    }
+}
+
+/// Touch the signal file of the prompt that last showed stale counts, so the
+/// shell redraws it with the cache just written
+fn signal_pending(cache_path: &Path) {
+    let pending = git::watch_pending_path(cache_path);
+    if let Ok(signal) = fs::read_to_string(&pending) {
+        let _ = fs::remove_file(&pending);
+        let _ = git::touch_signal_file(Path::new(signal.trim()));
+    }
+}
+
+/// Watch one directory; it may already be gone again
+fn watch_dir(watcher: &mut impl Watcher, dir: &Path) -> Result<()> {
+    match watcher.watch(dir, RecursiveMode::NonRecursive) {
+        Err(e) if matches!(e.kind, notify::ErrorKind::PathNotFound) => Ok(()),
+        Err(notify::Error {
+            kind: notify::ErrorKind::Io(ref io),
+            ..
+        }) if io.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        result => result.with_context(|| format!("Failed to watch {}", dir.display())),
+    }
+}
+
+/// The lock file still holds our PID (another watcher may have replaced us)
+fn owns_lock(lock_path: &Path) -> bool {
+    fs::read_to_string(lock_path).is_ok_and(|pid| pid.trim() == std::process::id().to_string())
+}
+
+/// Time since a prompt last touched the lock file
+fn heartbeat_age(lock_path: &Path) -> Duration {
+    fs::metadata(lock_path)
+        .and_then(|meta| meta.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .unwrap_or_default()
+}
+
+/// A changed path and its line stats relative to HEAD
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    flags: Status,
+    added: usize,
+    deleted: usize,
+}
+
+/// What an event path means for the status
+#[derive(Debug, PartialEq, Eq)]
+enum Change {
+    /// Ignored, inside `.git/objects`, a lock file, ...
+    None,
+    /// HEAD, the index or refs moved
+    Rescan,
+    /// A work tree path relative to the root
+    Path(String),
+}
+
+/// Changed paths of one work tree, keyed like `git status` paths (untracked
+/// directories end in `/`)
+struct RepoWatch {
+    repo: Repository,
+    root: PathBuf,
+    git_dir: PathBuf,
+    common_dir: PathBuf,
+    entries: HashMap<String, Entry>,
+    submodules: Vec<String>,
+    submodules_dirty: usize,
+    dirty_ts: i64,
+    fingerprint: String,
+}
+
+impl RepoWatch {
+    fn open(root: &Path) -> Result<Self> {
+        let repo = Repository::open(root)
+            .with_context(|| format!("Not a git work tree: {}", root.display()))?;
+        let git_dir = repo.path().to_path_buf();
+        // Linked worktrees share refs with the main repository via "commondir"
+        let common_dir = fs::read_to_string(git_dir.join("commondir"))
+            .ok()
+            .and_then(|common| git_dir.join(common.trim()).canonicalize().ok())
+            .unwrap_or_else(|| git_dir.clone());
+        Ok(Self {
+            root: root.to_path_buf(),
+            git_dir,
+            common_dir,
+            repo,
+            entries: HashMap::new(),
+            submodules: Vec::new(),
+            submodules_dirty: 0,
+            dirty_ts: 0,
+            fingerprint: String::new(),
+        })
+    }
+
+    /// Full scan, as the background worker does it
+    fn rescan(&mut self) -> Result<()> {
+        self.fingerprint = git::repo_fingerprint(&self.root).unwrap_or_default();
+        // Reopen so nothing cached from the old index or HEAD survives
+        self.repo = Repository::open(&self.root)?;
+        self.entries.clear();
+        self.dirty_ts = 0;
+
+        let statuses: Vec<(String, Status)> = self
+            .repo
+            .statuses(Some(&mut git::status_options()))?
+            .iter()
+            .filter_map(|entry| Some((entry.path()?.to_string(), entry.status())))
+            .collect();
+        for (path, flags) in statuses {
+            self.touch_dirty_ts(&path);
+            self.entries.insert(
+                path,
+                Entry {
+                    flags,
+                    added: 0,
+                    deleted: 0,
+                },
+            );
+        }
+
+        // Line stats per file, so single paths can be updated later
+        let head_tree = self.repo.head().ok().and_then(|h| h.peel_to_tree().ok());
+        let mut opts = git2::DiffOptions::new();
+        opts.ignore_submodules(true);
+        let diff = self
+            .repo
+            .diff_tree_to_workdir_with_index(head_tree.as_ref(), Some(&mut opts))?;
+        for (idx, delta) in diff.deltas().enumerate() {
+            let Some(path) = delta.new_file().path().or(delta.old_file().path()) else {
+                continue;
+            };
+            let Ok(Some(patch)) = git2::Patch::from_diff(&diff, idx) else {
+                continue;
+            };
+            let (_, added, deleted) = patch.line_stats().unwrap_or_default();
+            if let Some(entry) = self.entries.get_mut(path.to_string_lossy().as_ref()) {
+                entry.added = added;
+                entry.deleted = deleted;
+            }
+        }
+
+        self.submodules = self
+            .repo
+            .submodules()
+            .map(|subs| {
+                subs.iter()
+                    .filter_map(|sub| sub.path().to_str().map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default();
+        if git::submodules_enabled() {
+            self.submodules_dirty = git::count_dirty_submodules(&self.root);
+        }
+        Ok(())
+    }
+
+    /// Apply a burst of events; false when nothing relevant changed
+    fn apply(&mut self, events: &[notify::Result<Event>]) -> bool {
+        let mut changed = false;
+        let mut rescan = false;
+        let mut submodules = false;
+
+        for event in events {
+            // Dropped events (queue overflow) leave us not knowing what changed
+            let Ok(event) = event else {
+                rescan = true;
+                continue;
+            };
+            if matches!(event.kind, EventKind::Access(_)) {
+                continue;
+            }
+            let is_dir = matches!(
+                event.kind,
+                EventKind::Create(CreateKind::Folder) | EventKind::Remove(RemoveKind::Folder)
+            );
+
+            for path in &event.paths {
+                match self.classify(path) {
+                    Change::None => {}
+                    Change::Rescan => rescan = true,
+                    Change::Path(rel) if self.in_submodule(&rel) => submodules = true,
+                    Change::Path(rel) => {
+                        if is_dir || !self.update_path(&rel) {
+                            rescan = true;
+                        }
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        if rescan {
+            return self.rescan().is_ok();
+        }
+        if submodules && git::submodules_enabled() {
+            self.submodules_dirty = git::count_dirty_submodules(&self.root);
+            changed = true;
+        }
+        changed
+    }
+
+    /// Sort an event path into git metadata, work tree paths and noise
+    fn classify(&self, path: &Path) -> Change {
+        for git_dir in [&self.git_dir, &self.common_dir] {
+            if let Ok(rel) = path.strip_prefix(git_dir) {
+                let noise = rel.starts_with("objects")
+                    || rel.starts_with("logs")
+                    || rel.extension().is_some_and(|ext| ext == "lock");
+                return if noise { Change::None } else { Change::Rescan };
+            }
+        }
+
+        let Ok(rel) = path.strip_prefix(&self.root) else {
+            return Change::None;
+        };
+        if rel.as_os_str().is_empty() || rel.components().any(|c| c.as_os_str() == ".git") {
+            return Change::None;
+        }
+        if self.is_ignored(path) {
+            return Change::None;
+        }
+        let rel = rel.to_string_lossy().into_owned();
+        // New files inside an untracked directory are already counted by it
+        if self.in_untracked_dir(&rel) {
+            return Change::None;
+        }
+        Change::Path(rel)
+    }
+
+    /// `dir` and every directory below it that git doesn't ignore, skipping
+    /// `.git` dirs (watched separately) and symlinks
+    fn dirs_to_watch(&self, dir: &Path) -> Vec<PathBuf> {
+        let mut dirs = Vec::new();
+        let mut pending = vec![dir.to_path_buf()];
+        while let Some(dir) = pending.pop() {
+            if let Ok(entries) = fs::read_dir(&dir) {
+                for entry in entries.flatten() {
+                    if entry.file_type().is_ok_and(|kind| kind.is_dir())
+                        && entry.file_name() != ".git"
+                        && !self.is_ignored(&entry.path())
+                    {
+                        pending.push(entry.path());
+                    }
+                }
+            }
+            dirs.push(dir);
+        }
+        dirs
+    }
+
+    /// Directories created or moved into the work tree by a burst of events,
+    /// plus the root again when a `.gitignore` changed what is ignored
+    fn new_dirs(&self, events: &[notify::Result<Event>]) -> Vec<PathBuf> {
+        let mut dirs = Vec::new();
+        for event in events.iter().flatten() {
+            let created = matches!(
+                event.kind,
+                EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(_))
+            );
+            for path in &event.paths {
+                if path.file_name().is_some_and(|name| name == ".gitignore") {
+                    dirs.push(self.root.clone());
+                } else if created && path.is_dir() && matches!(self.classify(path), Change::Path(_))
+                {
+                    dirs.push(path.clone());
+                }
+            }
+        }
+        dirs.sort();
+        dirs.dedup();
+        dirs
+    }
+
+    fn is_ignored(&self, path: &Path) -> bool {
+        path.strip_prefix(&self.root)
+            .is_ok_and(|rel| self.repo.status_should_ignore(rel).unwrap_or(false))
+    }
+
+    /// Ask libgit2 about one path again; false when that isn't enough
+    fn update_path(&mut self, rel: &str) -> bool {
+        let abs = self.root.join(rel);
+        if abs.is_dir() {
+            return false;
+        }
+
+        let flags = match self.repo.status_file(Path::new(rel)) {
+            Ok(flags) => flags,
+            Err(_) if abs.exists() => return false,
+            Err(_) => {
+                // Gone and unknown to git: a deleted untracked file, or a
+                // tracked directory that was removed or renamed away
+                let tracked_dir = self
+                    .repo
+                    .index()
+                    .is_ok_and(|index| index.find_prefix(format!("{}/", rel)).is_ok());
+                self.entries.remove(rel);
+                return !tracked_dir;
+            }
+        };
+
+        let untracked_hidden =
+            flags.is_wt_new() && git::is_env_truthy("ZUSH_GIT_DISABLE_UNTRACKED");
+        if flags.is_empty() || flags.is_ignored() || untracked_hidden {
+            self.entries.remove(rel);
+            if self.entries.is_empty() {
+                self.dirty_ts = 0;
+            }
+            return true;
+        }
+
+        let (added, deleted) = self.line_stats(rel);
+        self.entries.insert(
+            rel.to_string(),
+            Entry {
+                flags,
+                added,
+                deleted,
+            },
+        );
+        self.touch_dirty_ts(rel);
+        true
+    }
+
+    /// Lines (inserted, deleted) of one path relative to HEAD
+    fn line_stats(&self, rel: &str) -> (usize, usize) {
+        let head_tree = self.repo.head().ok().and_then(|h| h.peel_to_tree().ok());
+        let mut opts = git2::DiffOptions::new();
+        opts.ignore_submodules(true)
+            .pathspec(rel)
+            .disable_pathspec_match(true);
+        self.repo
+            .diff_tree_to_workdir_with_index(head_tree.as_ref(), Some(&mut opts))
+            .and_then(|diff| diff.stats())
+            .map(|stats| (stats.insertions(), stats.deletions()))
+            .unwrap_or_default()
+    }
+
+    fn touch_dirty_ts(&mut self, rel: &str) {
+        if let Some(modified) = git::modified_secs(&self.root.join(rel)) {
+            if self.dirty_ts == 0 || modified < self.dirty_ts {
+                self.dirty_ts = modified;
+            }
+        }
+    }
+
+    fn in_untracked_dir(&self, rel: &str) -> bool {
+        self.entries.iter().any(|(path, entry)| {
+            path.ends_with('/') && entry.flags.is_wt_new() && rel.starts_with(path.as_str())
+        })
+    }
+
+    fn in_submodule(&self, rel: &str) -> bool {
+        self.submodules
+            .iter()
+            .any(|sub| rel == sub || rel.starts_with(&format!("{}/", sub)))
+    }
+
+    /// Counts for the status cache
+    fn status(&self) -> GitStatus {
+        let mut status = GitStatus {
+            submodules_dirty: self.submodules_dirty,
+            dirty_ts: self.dirty_ts,
+            fingerprint: self.fingerprint.clone(),
+            ..Default::default()
+        };
+        for entry in self.entries.values() {
+            git::count_entry(&mut status, entry.flags);
+            status.lines_added += entry.added;
+            status.lines_deleted += entry.deleted;
+        }
+        status
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn modify(path: PathBuf) -> notify::Result<Event> {
+        Ok(Event::new(EventKind::Modify(notify::event::ModifyKind::Any)).add_path(path))
+    }
+
+    #[test]
+    fn test_incremental_updates() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let repo = Repository::init(&root).unwrap();
+        fs::write(root.join(".gitignore"), "build/\n").unwrap();
+        fs::write(root.join("a.txt"), "one\n").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("a.txt")).unwrap();
+        index.add_path(Path::new(".gitignore")).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = git2::Signature::now("Ada", "ada@example.com").unwrap();
+        repo.commit(Some("HEAD"), &signature, &signature, "init", &tree, &[])
+            .unwrap();
+
+        let mut state = RepoWatch::open(&root).unwrap();
+        state.rescan().unwrap();
+        assert_eq!(state.status().modified, 0);
+
+        // Editing a tracked file only looks at that file
+        fs::write(root.join("a.txt"), "one\ntwo\n").unwrap();
+        assert!(state.apply(&[modify(root.join("a.txt"))]));
+        let status = state.status();
+        assert_eq!(status.modified, 1);
+        assert_eq!(status.lines_added, 1);
+        assert!(status.dirty_ts > 0);
+
+        // New untracked file, then an ignored one
+        fs::write(root.join("b.txt"), "b").unwrap();
+        assert!(state.apply(&[modify(root.join("b.txt"))]));
+        assert_eq!(state.status().untracked, 1);
+        fs::create_dir(root.join("build")).unwrap();
+        fs::write(root.join("build/out.o"), "").unwrap();
+        assert!(!state.apply(&[modify(root.join("build/out.o"))]));
+
+        // Deleting the untracked file and reverting the edit
+        fs::remove_file(root.join("b.txt")).unwrap();
+        fs::write(root.join("a.txt"), "one\n").unwrap();
+        assert!(state.apply(&[modify(root.join("b.txt")), modify(root.join("a.txt"))]));
+        let status = state.status();
+        assert_eq!((status.modified, status.untracked), (0, 0));
+        assert_eq!(status.dirty_ts, 0);
+
+        // Object writes are noise, index writes need a rescan
+        let git_dir = state.git_dir.clone();
+        assert_eq!(
+            state.classify(&git_dir.join("objects/ab/cdef")),
+            Change::None
+        );
+        assert_eq!(state.classify(&git_dir.join("index")), Change::Rescan);
+    }
+
+    #[test]
+    fn test_dirs_to_watch() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        Repository::init(&root).unwrap();
+        fs::write(root.join(".gitignore"), "build/\n").unwrap();
+        fs::create_dir_all(root.join("src/deep")).unwrap();
+        fs::create_dir_all(root.join("build/cache")).unwrap();
+
+        // Ignored trees and .git cost no watches
+        let state = RepoWatch::open(&root).unwrap();
+        let mut dirs = state.dirs_to_watch(&root);
+        dirs.sort();
+        assert_eq!(
+            dirs,
+            [root.clone(), root.join("src"), root.join("src/deep")]
+        );
+
+        // New directories get watched, new ignored ones don't
+        fs::create_dir_all(root.join("lib/util")).unwrap();
+        fs::create_dir(root.join("build/tmp")).unwrap();
+        let created =
+            |path: PathBuf| Ok(Event::new(EventKind::Create(CreateKind::Folder)).add_path(path));
+        let events = [created(root.join("lib")), created(root.join("build/tmp"))];
+        assert_eq!(state.new_dirs(&events), [root.join("lib")]);
+        assert_eq!(
+            state.dirs_to_watch(&root.join("lib")),
+            [root.join("lib"), root.join("lib/util")]
+        );
+    }
+}